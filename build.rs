@@ -8,21 +8,55 @@ fn main() {
 
 #[cfg(feature = "download")]
 mod download {
-    use bitcoin_hashes::{sha256, Hash};
+    use bitcoin_hashes::{sha256, Hash, HashEngine};
     use std::fs::File;
-    use std::io::{BufRead, BufReader, Cursor};
+    use std::io::{BufRead, BufReader, Read, Write};
+    #[cfg(unix)]
     use std::os::unix::fs::PermissionsExt;
-    use std::path::Path;
+    use std::path::{Path, PathBuf};
     use std::str::FromStr;
 
-    include!("src/versions.rs");
+    // the cfg in versions.rs are evaluated for the host here, only the version ones are used
+    #[allow(dead_code)]
+    mod versions {
+        include!("src/versions.rs");
+    }
+    use versions::{HAS_FEATURE, VERSION};
 
     const GITHUB_URL: &str =
         "https://github.com/RCasatta/electrsd/releases/download/electrs_releases";
+    const ELECTRS_GIT_URL: &str = "https://github.com/romanz/electrs";
+    const ESPLORA_GIT_URL: &str = "https://github.com/Blockstream/electrs";
+
+    const DOWNLOAD_ATTEMPTS: u32 = 3;
+    const DOWNLOAD_TIMEOUT_SECS: u64 = 300;
+
+    /// Name of the OS the crate is built for, as used in the prebuilt asset names
+    fn target_os() -> String {
+        let os = std::env::var("CARGO_CFG_TARGET_OS").unwrap();
+        match os.as_str() {
+            "linux" | "macos" | "windows" => os,
+            _ => "undefined".to_string(),
+        }
+    }
+
+    /// Prebuilt assets exist only for x86_64, other architectures need build-from-source on linux.
+    /// The macOS and windows ones run also on arm through Rosetta and the windows emulation.
+    fn has_prebuilt_arch() -> bool {
+        target_os() != "linux" || std::env::var("CARGO_CFG_TARGET_ARCH").unwrap() == "x86_64"
+    }
+
+    fn exe_name() -> &'static str {
+        if target_os() == "windows" {
+            "electrs.exe"
+        } else {
+            "electrs"
+        }
+    }
 
     fn get_expected_sha256(filename: &str) -> Result<sha256::Hash, ()> {
         let file = File::open("sha256").map_err(|_| ())?;
-        for line in BufReader::new(file).lines().flatten() {
+        for line in BufReader::new(file).lines().map_while(Result::ok) {
             let tokens: Vec<_> = line.split("  ").collect();
             if tokens.len() == 2 && filename == tokens[1] {
                 return sha256::Hash::from_str(tokens[0]).map_err(|_| ());
@@ -31,6 +65,31 @@ mod download {
         Err(())
     }
 
+    /// Stream the archive at `url` to `path` hashing it on the way, to avoid buffering it in memory
+    fn download_to(url: &str, path: &Path) -> Result<sha256::Hash, String> {
+        let mut response = minreq::get(url)
+            .with_timeout(DOWNLOAD_TIMEOUT_SECS)
+            .send_lazy()
+            .map_err(|e| e.to_string())?;
+        if response.status_code != 200 {
+            return Err(format!("status code {}", response.status_code));
+        }
+        let mut download_file = File::create(path).map_err(|e| e.to_string())?;
+        let mut engine = sha256::Hash::engine();
+        let mut buffer = [0u8; 64 * 1024];
+        loop {
+            let read = response.read(&mut buffer).map_err(|e| e.to_string())?;
+            if read == 0 {
+                break;
+            }
+            engine.input(&buffer[..read]);
+            download_file
+                .write_all(&buffer[..read])
+                .map_err(|e| e.to_string())?;
+        }
+        Ok(sha256::Hash::from_engine(engine))
+    }
+
     pub fn download() {
         if std::env::var_os("ELECTRSD_SKIP_DOWNLOAD").is_some() {
             return;
@@ -39,45 +98,156 @@ mod download {
         if !HAS_FEATURE {
             return;
         }
-        let download_filename_without_extension = electrs_name();
+        // must match `versions::electrs_name` as compiled for the target
+        let download_filename_without_extension = format!("electrs_{}_{}", target_os(), VERSION);
         let download_filename = format!("{}.zip", download_filename_without_extension);
         dbg!(&download_filename);
-        let expected_hash = get_expected_sha256(&download_filename).unwrap();
         let out_dir = std::env::var_os("OUT_DIR").unwrap();
         let electrs_exe_home = Path::new(&out_dir).join("electrs");
-        let destination_filename = electrs_exe_home
-            .join(&download_filename_without_extension)
-            .join("electrs");
+        println!("cargo:rerun-if-env-changed=ELECTRSD_DOWNLOAD_DEST");
+        println!("cargo:rerun-if-env-changed=ELECTRSD_DOWNLOAD_DIR");
+        let destination_filename = match (
+            std::env::var_os("ELECTRSD_DOWNLOAD_DEST"),
+            std::env::var_os("ELECTRSD_DOWNLOAD_DIR"),
+        ) {
+            (Some(path), _) => PathBuf::from(path),
+            (None, Some(dir)) => Path::new(&dir)
+                .join(&download_filename_without_extension)
+                .join(exe_name()),
+            (None, None) => electrs_exe_home
+                .join(&download_filename_without_extension)
+                .join(exe_name()),
+        };
 
         dbg!(&destination_filename);
 
-        if !destination_filename.exists() {
-            println!(
-                "filename:{} version:{} hash:{}",
-                download_filename, VERSION, expected_hash
-            );
+        if destination_filename.exists() {
+            return;
+        }
 
-            let download_endpoint =
-                std::env::var("ELECTRSD_DOWNLOAD_ENDPOINT").unwrap_or(GITHUB_URL.to_string());
-            let url = format!("{}/{}", download_endpoint, download_filename);
+        let expected_hash = match get_expected_sha256(&download_filename) {
+            Ok(hash) if has_prebuilt_arch() => hash,
+            _ if cfg!(feature = "build-from-source") => {
+                build_from_source(Path::new(&out_dir), &destination_filename);
+                return;
+            }
+            _ => panic!(
+                "no prebuilt {} for the {} architecture, enable the build-from-source feature or set ELECTRSD_SKIP_DOWNLOAD and provide electrs with ELECTRS_EXEC",
+                download_filename,
+                std::env::var("CARGO_CFG_TARGET_ARCH").unwrap()
+            ),
+        };
 
-            let downloaded_bytes = minreq::get(url).send().unwrap().into_bytes();
+        println!(
+            "filename:{} version:{} hash:{}",
+            download_filename, VERSION, expected_hash
+        );
 
-            let downloaded_hash = sha256::Hash::hash(&downloaded_bytes);
-            assert_eq!(expected_hash, downloaded_hash);
-            let cursor = Cursor::new(downloaded_bytes);
+        println!("cargo:rerun-if-env-changed=ELECTRSD_DOWNLOAD_URL_TEMPLATE");
+        let url = match std::env::var("ELECTRSD_DOWNLOAD_URL_TEMPLATE") {
+            Ok(template) => template
+                .replace("{os}", &target_os())
+                .replace("{version}", VERSION)
+                .replace("{filename}", &download_filename),
+            Err(_) => {
+                let download_endpoint =
+                    std::env::var("ELECTRSD_DOWNLOAD_ENDPOINT").unwrap_or(GITHUB_URL.to_string());
+                format!("{}/{}", download_endpoint, download_filename)
+            }
+        };
+
+        let download_path = Path::new(&out_dir).join(&download_filename);
+        let mut attempt = 1;
+        let downloaded_hash = loop {
+            match download_to(&url, &download_path) {
+                Ok(hash) => break hash,
+                Err(e) if attempt < DOWNLOAD_ATTEMPTS => {
+                    println!("cargo:warning=download of {} failed: {}, retrying", url, e);
+                    std::thread::sleep(std::time::Duration::from_secs(1 << attempt));
+                    attempt += 1;
+                }
+                Err(e) => panic!(
+                    "download of {} failed {} times, last error: {}. Set ELECTRSD_SKIP_DOWNLOAD and provide electrs with ELECTRS_EXEC to build offline",
+                    url, DOWNLOAD_ATTEMPTS, e
+                ),
+            }
+        };
+        assert_eq!(expected_hash, downloaded_hash);
 
-            let mut archive = zip::ZipArchive::new(cursor).unwrap();
-            let mut file = archive.by_index(0).unwrap();
-            std::fs::create_dir_all(destination_filename.parent().unwrap()).unwrap();
-            let mut outfile = std::fs::File::create(&destination_filename).unwrap();
+        let mut archive = zip::ZipArchive::new(File::open(&download_path).unwrap()).unwrap();
+        let mut file = archive.by_index(0).unwrap();
+        std::fs::create_dir_all(destination_filename.parent().unwrap()).unwrap();
+        let extracting_filename = partial_path(&destination_filename);
+        let mut outfile = std::fs::File::create(&extracting_filename).unwrap();
 
-            std::io::copy(&mut file, &mut outfile).unwrap();
-            std::fs::set_permissions(
-                &destination_filename,
-                std::fs::Permissions::from_mode(0o755),
-            )
+        std::io::copy(&mut file, &mut outfile).unwrap();
+        drop(outfile);
+        #[cfg(unix)]
+        std::fs::set_permissions(&extracting_filename, std::fs::Permissions::from_mode(0o755))
             .unwrap();
+        persist(&extracting_filename, &destination_filename);
+        std::fs::remove_file(&download_path).unwrap();
+    }
+
+    /// Unique path next to `destination` where the executable is written before [persist], so
+    /// that concurrent builds sharing ELECTRSD_DOWNLOAD_DIR never see a partially written one
+    fn partial_path(destination: &Path) -> PathBuf {
+        let nanos = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .subsec_nanos();
+        destination.with_extension(format!("{}.{}.partial", std::process::id(), nanos))
+    }
+
+    /// Atomically move the `partial` executable to `destination`, if it fails because a
+    /// concurrent build already created `destination` that one is kept
+    fn persist(partial: &Path, destination: &Path) {
+        if let Err(e) = std::fs::rename(partial, destination) {
+            let _ = std::fs::remove_file(partial);
+            assert!(
+                destination.exists(),
+                "cannot move {} to {}: {}",
+                partial.display(),
+                destination.display(),
+                e
+            );
         }
     }
+
+    /// Compile the pinned electrs `VERSION` with `cargo install`, for the platforms without a
+    /// prebuilt asset, and move the executable to `destination_filename`
+    fn build_from_source(out_dir: &Path, destination_filename: &Path) {
+        let (git, revision) = match VERSION.strip_prefix("esplora_") {
+            Some(commit) => (ESPLORA_GIT_URL, ["--rev", commit]),
+            None => (ELECTRS_GIT_URL, ["--tag", VERSION]),
+        };
+        let install_root = out_dir.join("electrs_source");
+        println!(
+            "cargo:warning=no prebuilt electrs {} for this platform, building it from {}",
+            VERSION, git
+        );
+        let cargo = std::env::var_os("CARGO").unwrap_or_else(|| "cargo".into());
+        let status = std::process::Command::new(cargo)
+            .args(["install", "--locked", "--git", git])
+            .args(revision)
+            .arg("--root")
+            .arg(&install_root)
+            .arg("--target")
+            .arg(std::env::var("TARGET").unwrap())
+            .arg("--target-dir")
+            .arg(install_root.join("target"))
+            .arg("electrs")
+            .status()
+            .unwrap_or_else(|e| panic!("failed to run cargo install: {}", e));
+        assert!(
+            status.success(),
+            "building electrs {} from source failed with {}",
+            VERSION,
+            status
+        );
+        std::fs::create_dir_all(destination_filename.parent().unwrap()).unwrap();
+        let partial = partial_path(destination_filename);
+        std::fs::copy(install_root.join("bin").join(exe_name()), &partial).unwrap();
+        persist(&partial, destination_filename);
+    }
 }
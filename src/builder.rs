@@ -0,0 +1,72 @@
+//! Builder of [Conf], a construction path not broken by new fields
+//!
+
+use std::path::PathBuf;
+
+use crate::Conf;
+
+/// Builder of [Conf], starting from [Conf::default], see [Conf::builder]
+///
+/// ```
+/// let conf = electrsd::Conf::builder()
+///     .view_stderr(true)
+///     .network("regtest")
+///     .attempts(5)
+///     .build();
+/// assert!(conf.view_stderr);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct ConfBuilder<'a> {
+    conf: Conf<'a>,
+}
+
+impl<'a> ConfBuilder<'a> {
+    /// Set [Conf::args]
+    pub fn args(mut self, args: Vec<&'a str>) -> Self {
+        self.conf.args = args;
+        self
+    }
+
+    /// Set [Conf::view_stderr]
+    pub fn view_stderr(mut self, view_stderr: bool) -> Self {
+        self.conf.view_stderr = view_stderr;
+        self
+    }
+
+    /// Set [Conf::http_enabled]
+    pub fn http_enabled(mut self, http_enabled: bool) -> Self {
+        self.conf.http_enabled = http_enabled;
+        self
+    }
+
+    /// Set [Conf::network]
+    pub fn network(mut self, network: &'a str) -> Self {
+        self.conf.network = network;
+        self
+    }
+
+    /// Set [Conf::staticdir]
+    pub fn staticdir<P: Into<PathBuf>>(mut self, staticdir: P) -> Self {
+        self.conf.staticdir = Some(staticdir.into());
+        self
+    }
+
+    /// Set how many times electrs is launched again when it exits early, for example because
+    /// another process took one of its ports. With 0 the first early exit is returned.
+    pub fn attempts(mut self, attempts: u8) -> Self {
+        self.conf.attempts = attempts;
+        self
+    }
+
+    /// Return the built [Conf]
+    pub fn build(self) -> Conf<'a> {
+        self.conf
+    }
+}
+
+impl<'a> Conf<'a> {
+    /// Return a [ConfBuilder] starting from the default configuration
+    pub fn builder() -> ConfBuilder<'a> {
+        ConfBuilder::default()
+    }
+}
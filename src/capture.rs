@@ -0,0 +1,77 @@
+//! Capture of the electrs stderr
+//!
+
+use std::collections::VecDeque;
+use std::io::{BufRead, BufReader, Read};
+use std::process::Child;
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+/// Keeps the last lines written by electrs on stderr, see [crate::Conf::capture_stderr]
+#[derive(Clone)]
+pub(crate) struct StderrCapture {
+    lines: Arc<Mutex<VecDeque<String>>>,
+    max_lines: Option<usize>,
+    echo: bool,
+}
+
+impl StderrCapture {
+    /// Create a capture keeping at most `max_lines` lines, if `echo` lines are also written to
+    /// the stderr of the current process
+    pub(crate) fn new(max_lines: Option<usize>, echo: bool) -> Self {
+        StderrCapture {
+            lines: Arc::new(Mutex::new(VecDeque::new())),
+            max_lines,
+            echo,
+        }
+    }
+
+    /// Start reading the piped stderr of the given process in a thread, which ends when the
+    /// process exits
+    pub(crate) fn start(&self, process: &mut Child) -> Option<JoinHandle<()>> {
+        let stderr = process.stderr.take()?;
+        let capture = self.clone();
+        Some(thread::spawn(move || capture.read(stderr)))
+    }
+
+    /// Read the lines of `reader` until it ends, dropping the oldest ones beyond `max_lines`
+    fn read<R: Read>(&self, reader: R) {
+        for line in BufReader::new(reader).lines().map_while(Result::ok) {
+            if self.echo {
+                eprintln!("{}", line);
+            }
+            let mut lines = self.lines.lock().unwrap();
+            lines.push_back(line);
+            while self.max_lines.is_some_and(|max| lines.len() > max) {
+                lines.pop_front();
+            }
+        }
+    }
+
+    /// Return the captured lines, oldest first
+    pub(crate) fn lines(&self) -> Vec<String> {
+        self.lines.lock().unwrap().iter().cloned().collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::capture::StderrCapture;
+
+    #[test]
+    fn test_max_lines() {
+        let capture = StderrCapture::new(Some(2), false);
+        capture.read("first\nsecond\nthird\n".as_bytes());
+        assert_eq!(capture.lines(), vec!["second", "third"]);
+        capture.read("fourth\n".as_bytes());
+        assert_eq!(capture.lines(), vec!["third", "fourth"]);
+
+        let capture = StderrCapture::new(Some(0), false);
+        capture.read("first\n".as_bytes());
+        assert!(capture.lines().is_empty());
+
+        let capture = StderrCapture::new(None, false);
+        capture.read("first\nsecond\nthird\n".as_bytes());
+        assert_eq!(capture.lines(), vec!["first", "second", "third"]);
+    }
+}
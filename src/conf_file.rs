@@ -0,0 +1,250 @@
+//! Owned counterpart of [Conf] to load it from configuration files
+//!
+
+use std::net::IpAddr;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use bitcoind::bitcoincore_rpc::jsonrpc::serde_json::Value;
+
+use crate::{Auth, Conf, WaitConfig};
+
+/// [Conf] with owned fields, so that it can be deserialized from any source, like readers, TOML
+/// files or strings containing escape sequences, which can't be deserialized into the borrowed
+/// fields of [Conf].
+///
+/// Missing fields take the [Conf::default] values, [Conf::spawn_hook] isn't serialized.
+///
+/// ```
+/// # use electrsd::bitcoind::bitcoincore_rpc::jsonrpc::serde_json;
+/// let reader = r#"{"network": "signet", "args": ["--index-batch-size", "10"]}"#.as_bytes();
+/// let conf_file: electrsd::ConfFile = serde_json::from_reader(reader).unwrap();
+/// let conf = electrsd::Conf::from(&conf_file);
+/// assert_eq!(conf.network, "signet");
+/// ```
+#[derive(Debug, PartialEq, Eq, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct ConfFile {
+    args: Vec<String>,
+    view_stderr: bool,
+    verbosity: u8,
+    capture_stderr: bool,
+    stderr_buffer_lines: Option<usize>,
+    http_enabled: bool,
+    monitoring: bool,
+    network: String,
+    allow_unknown_network: bool,
+    skip_ibd_check: bool,
+    listen_addr: IpAddr,
+    connect_host: Option<String>,
+    electrum_port: Option<u16>,
+    esplora_port: Option<u16>,
+    auth: AuthFile,
+    tmpdir: Option<PathBuf>,
+    staticdir: Option<PathBuf>,
+    reindex: bool,
+    db_parallelism: Option<usize>,
+    precache_scripts: Option<PathBuf>,
+    daemon_dir: Option<PathBuf>,
+    config_file: Option<PathBuf>,
+    index_batch_size: Option<usize>,
+    index_lookup_limit: Option<usize>,
+    jsonrpc_timeout: Option<Duration>,
+    server_banner: Option<String>,
+    env: Vec<(String, String)>,
+    process_group: bool,
+    startup_timeout: Option<Duration>,
+    kill_timeout: Duration,
+    electrs_ready_timeout: Duration,
+    post_start: Vec<(String, Vec<Value>)>,
+    verify_genesis: bool,
+    wait: WaitConfig,
+    attempts: u8,
+}
+
+/// Owned [Auth], with the same serialized representation
+#[derive(Debug, PartialEq, Eq, Clone, serde::Serialize, serde::Deserialize)]
+enum AuthFile {
+    Cookie,
+    UserPass { user: String, pass: String },
+}
+
+impl Default for ConfFile {
+    fn default() -> Self {
+        ConfFile::from(&Conf::default())
+    }
+}
+
+impl From<&Conf<'_>> for ConfFile {
+    fn from(conf: &Conf<'_>) -> Self {
+        ConfFile {
+            args: conf.args.iter().map(|arg| arg.to_string()).collect(),
+            view_stderr: conf.view_stderr,
+            verbosity: conf.verbosity,
+            capture_stderr: conf.capture_stderr,
+            stderr_buffer_lines: conf.stderr_buffer_lines,
+            http_enabled: conf.http_enabled,
+            monitoring: conf.monitoring,
+            network: conf.network.to_string(),
+            allow_unknown_network: conf.allow_unknown_network,
+            skip_ibd_check: conf.skip_ibd_check,
+            listen_addr: conf.listen_addr,
+            connect_host: conf.connect_host.map(str::to_string),
+            electrum_port: conf.electrum_port,
+            esplora_port: conf.esplora_port,
+            auth: match conf.auth {
+                Auth::Cookie => AuthFile::Cookie,
+                Auth::UserPass { user, pass } => AuthFile::UserPass {
+                    user: user.to_string(),
+                    pass: pass.to_string(),
+                },
+            },
+            tmpdir: conf.tmpdir.clone(),
+            staticdir: conf.staticdir.clone(),
+            reindex: conf.reindex,
+            db_parallelism: conf.db_parallelism,
+            precache_scripts: conf.precache_scripts.clone(),
+            daemon_dir: conf.daemon_dir.clone(),
+            config_file: conf.config_file.clone(),
+            index_batch_size: conf.index_batch_size,
+            index_lookup_limit: conf.index_lookup_limit,
+            jsonrpc_timeout: conf.jsonrpc_timeout,
+            server_banner: conf.server_banner.clone(),
+            env: conf
+                .env
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect(),
+            process_group: conf.process_group,
+            startup_timeout: conf.startup_timeout,
+            kill_timeout: conf.kill_timeout,
+            electrs_ready_timeout: conf.electrs_ready_timeout,
+            post_start: conf
+                .post_start
+                .iter()
+                .map(|(method, params)| (method.to_string(), params.clone()))
+                .collect(),
+            verify_genesis: conf.verify_genesis,
+            wait: conf.wait,
+            attempts: conf.attempts,
+        }
+    }
+}
+
+impl<'a> From<&'a ConfFile> for Conf<'a> {
+    fn from(file: &'a ConfFile) -> Self {
+        Conf {
+            args: file.args.iter().map(String::as_str).collect(),
+            view_stderr: file.view_stderr,
+            verbosity: file.verbosity,
+            capture_stderr: file.capture_stderr,
+            stderr_buffer_lines: file.stderr_buffer_lines,
+            http_enabled: file.http_enabled,
+            monitoring: file.monitoring,
+            network: &file.network,
+            allow_unknown_network: file.allow_unknown_network,
+            skip_ibd_check: file.skip_ibd_check,
+            listen_addr: file.listen_addr,
+            connect_host: file.connect_host.as_deref(),
+            electrum_port: file.electrum_port,
+            esplora_port: file.esplora_port,
+            auth: match &file.auth {
+                AuthFile::Cookie => Auth::Cookie,
+                AuthFile::UserPass { user, pass } => Auth::UserPass { user, pass },
+            },
+            tmpdir: file.tmpdir.clone(),
+            staticdir: file.staticdir.clone(),
+            reindex: file.reindex,
+            db_parallelism: file.db_parallelism,
+            precache_scripts: file.precache_scripts.clone(),
+            daemon_dir: file.daemon_dir.clone(),
+            config_file: file.config_file.clone(),
+            index_batch_size: file.index_batch_size,
+            index_lookup_limit: file.index_lookup_limit,
+            jsonrpc_timeout: file.jsonrpc_timeout,
+            server_banner: file.server_banner.clone(),
+            env: file
+                .env
+                .iter()
+                .map(|(key, value)| (key.as_str(), value.as_str()))
+                .collect(),
+            spawn_hook: None,
+            process_group: file.process_group,
+            startup_timeout: file.startup_timeout,
+            kill_timeout: file.kill_timeout,
+            electrs_ready_timeout: file.electrs_ready_timeout,
+            post_start: file
+                .post_start
+                .iter()
+                .map(|(method, params)| (method.as_str(), params.clone()))
+                .collect(),
+            verify_genesis: file.verify_genesis,
+            wait: file.wait,
+            attempts: file.attempts,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use bitcoind::bitcoincore_rpc::jsonrpc::serde_json;
+    use serde::de::value::{Error, MapDeserializer};
+    use serde::Deserialize;
+
+    use crate::{Auth, Conf, ConfFile};
+
+    #[test]
+    fn test_conf_file_round_trip() {
+        assert_eq!(Conf::from(&ConfFile::default()), Conf::default());
+
+        let conf = Conf {
+            args: vec!["--index-batch-size", "10"],
+            network: "signet",
+            connect_host: Some("localhost"),
+            auth: Auth::UserPass {
+                user: "alice",
+                pass: "secret",
+            },
+            env: vec![("RUST_LOG", "debug")],
+            post_start: vec![("getblockcount", vec![])],
+            ..Default::default()
+        };
+        let json = serde_json::to_string(&ConfFile::from(&conf)).unwrap();
+        let conf_file: ConfFile = serde_json::from_reader(json.as_bytes()).unwrap();
+        assert_eq!(Conf::from(&conf_file), conf);
+    }
+
+    #[test]
+    fn test_conf_file_escaped() {
+        let dir = crate::TempDir::new().unwrap();
+        let path = dir.path().join("conf.json");
+        std::fs::write(
+            &path,
+            r#"{"network": "signet", "server_banner": "line 1\nline 2"}"#,
+        )
+        .unwrap();
+
+        let file = std::fs::File::open(&path).unwrap();
+        let conf_file: ConfFile = serde_json::from_reader(file).unwrap();
+        let conf = Conf::from(&conf_file);
+        assert_eq!(conf.network, "signet");
+        assert_eq!(conf.server_banner.as_deref(), Some("line 1\nline 2"));
+        assert!(!conf.http_enabled);
+    }
+
+    #[test]
+    fn test_conf_file_owned_strings() {
+        // TOML and other formats hand owned strings to the visitor
+        let map = || {
+            let mut map = HashMap::new();
+            map.insert("network".to_string(), "testnet".to_string());
+            MapDeserializer::<_, Error>::new(map.into_iter())
+        };
+        let conf_file = ConfFile::deserialize(map()).unwrap();
+        assert_eq!(Conf::from(&conf_file).network, "testnet");
+
+        assert!(Conf::deserialize(map()).is_err());
+    }
+}
@@ -17,8 +17,37 @@ pub enum Error {
     #[cfg(not(target_os = "windows"))]
     Nix(nix::Error),
 
-    /// Wrapper of early exit status
-    EarlyExit(std::process::ExitStatus),
+    /// Returned when the electrs process exits before accepting connections, with the ports
+    /// allocated for the launch
+    EarlyExit {
+        /// Exit status of the process
+        status: std::process::ExitStatus,
+        /// Command line of the launch, executable path and arguments, with the bitcoind
+        /// credentials redacted
+        command: String,
+        /// Port allocated for the electrum RPC
+        electrum_port: u16,
+        /// Port allocated for the monitoring endpoint, if enabled
+        monitoring_port: Option<u16>,
+        /// Port allocated for the esplora endpoint, if enabled
+        esplora_port: Option<u16>,
+        /// Last lines written by electrs on stderr, empty if [crate::Conf::capture_stderr] is
+        /// disabled
+        stderr: Vec<String>,
+    },
+
+    /// Returned when electrs doesn't accept connections within [crate::Conf::startup_timeout],
+    /// contains how long it has been waited
+    StartupTimeout(std::time::Duration),
+
+    /// Returned by [crate::ElectrsD::kill] when electrs didn't exit within
+    /// [crate::Conf::kill_timeout] and has been forcibly killed, contains how long it has been
+    /// waited
+    KillTimeout(std::time::Duration),
+
+    /// Returned by [crate::ElectrsD::trigger], [crate::ElectrsD::pause] and
+    /// [crate::ElectrsD::resume] on windows, where signals aren't available
+    SignalUnsupported,
 
     /// Returned when both tmpdir and staticdir is specified in `Conf` options
     BothDirsSpecified,
@@ -29,6 +58,103 @@ pub enum Error {
 
     /// Returned if both env vars `ELECTRS_EXEC` and `ELECTRS_EXE` are found
     BothEnvVars,
+
+    /// Returned by [crate::ElectrsD::new_with_retries] when every attempt failed
+    AttemptsExhausted {
+        /// Number of launches attempted
+        attempts: usize,
+        /// Description of the error of the last attempt
+        last_error: String,
+    },
+
+    /// Returned when [crate::Conf::args] contains a flag automatically set by this crate
+    ReservedFlag(String),
+
+    /// Returned when electrs didn't catch up with bitcoind in the given time
+    SyncTimeout {
+        /// Height indexed by electrs when the timeout expired
+        electrs_height: usize,
+        /// Height of bitcoind when the timeout expired
+        bitcoind_height: usize,
+    },
+
+    /// Returned by [crate::validate_exe] when the electrs executable version requires different
+    /// arguments than the ones used by the enabled version feature
+    VersionFeatureMismatch {
+        /// Version of the enabled feature, `NA` if none is enabled
+        feature: &'static str,
+        /// Version reported by the executable
+        detected: String,
+    },
+
+    /// Returned when [crate::Conf::verify_genesis] is set and the genesis block of electrs doesn't
+    /// match the one of [crate::Conf::network]
+    GenesisMismatch {
+        /// Genesis block hash of the configured network
+        expected: electrum_client::bitcoin::BlockHash,
+        /// Genesis block hash returned by electrs
+        found: electrum_client::bitcoin::BlockHash,
+    },
+
+    /// Returned by [crate::ElectrsD::assert_synced_to] when the electrs tip differs from the
+    /// bitcoind one
+    NotSynced {
+        /// Height of the electrs tip
+        electrs_height: usize,
+        /// Hash of the electrs tip
+        electrs_hash: electrum_client::bitcoin::BlockHash,
+        /// Height of the bitcoind tip
+        bitcoind_height: usize,
+        /// Hash of the bitcoind tip
+        bitcoind_hash: electrum_client::bitcoin::BlockHash,
+    },
+
+    /// Returned by the wait helpers like [crate::ElectrsD::try_wait_height] when electrs didn't
+    /// index what was waited for in time
+    IndexTimeout {
+        /// What electrs was expected to index, like `height 101` or `tx <txid>`
+        expected: String,
+        /// How long it has been waited
+        waited: std::time::Duration,
+    },
+
+    /// Returned by [crate::EsploraClient] when esplora answers with an unexpected HTTP status
+    #[cfg(feature = "esplora")]
+    EsploraStatus {
+        /// Url of the request
+        url: String,
+        /// HTTP status code of the response
+        status_code: i32,
+    },
+
+    /// Returned when [crate::Conf::network] doesn't match the bitcoind chain
+    NetworkMismatch {
+        /// The configured electrs network
+        expected: String,
+        /// The chain reported by bitcoind `getblockchaininfo`
+        actual: String,
+    },
+
+    /// Returned when bitcoind doesn't have the p2p port open, required by electrs versions fetching
+    /// blocks through p2p, enable it with `bitcoind::Conf::p2p`
+    P2pPortRequired,
+
+    /// Returned when a fixed port like [crate::Conf::electrum_port] is already in use
+    PortInUse(u16),
+
+    /// Returned when the bitcoind cookie file is not found in any of the expected locations
+    CookieFileNotFound {
+        /// The paths where the cookie file has been searched
+        searched: Vec<std::path::PathBuf>,
+    },
+
+    /// Returned by [crate::ElectrsD::restart] on legacy versions when the bitcoind cookie changed
+    /// since electrs was launched, for example because bitcoind restarted. The old cookie value is
+    /// on the electrs command line, a new [crate::ElectrsD] is needed to use the new one.
+    StaleCookie {
+        /// The bitcoind cookie file
+        cookie_file: std::path::PathBuf,
+    },
 }
 
 impl std::error::Error for Error {
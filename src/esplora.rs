@@ -0,0 +1,153 @@
+//! Minimal client of the esplora HTTP API exposed when [crate::Conf::http_enabled] is `true`
+//!
+
+use std::collections::HashMap;
+
+use bitcoind::anyhow;
+use bitcoind::bitcoincore_rpc::jsonrpc::serde_json;
+use electrum_client::bitcoin::consensus::deserialize;
+use electrum_client::bitcoin::{Transaction, Txid};
+
+use crate::{ElectrsD, Error};
+
+/// Tiny blocking client of the esplora endpoint, see [ElectrsD::esplora_client]
+#[derive(Debug, Clone)]
+pub struct EsploraClient {
+    base_url: String,
+}
+
+impl EsploraClient {
+    /// Create a client for the esplora endpoint at the given url, eg. `127.0.0.1:3002`
+    pub fn new(esplora_url: &str) -> Self {
+        let base_url = if esplora_url.starts_with("http://") || esplora_url.starts_with("https://")
+        {
+            esplora_url.to_string()
+        } else {
+            format!("http://{}", esplora_url)
+        };
+        EsploraClient { base_url }
+    }
+
+    /// Return the base url used for the requests
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    /// Return the transaction with the given txid, `None` if esplora doesn't know it
+    pub fn get_tx(&self, txid: &Txid) -> anyhow::Result<Option<Transaction>> {
+        let response = self.get(&format!("/tx/{}/raw", txid))?;
+        match response.status_code {
+            200 => Ok(Some(deserialize(response.as_bytes())?)),
+            404 => Ok(None),
+            _ => Err(self.status_error(&response).into()),
+        }
+    }
+
+    /// Return the height of the tip indexed by esplora
+    pub fn get_block_height(&self) -> anyhow::Result<usize> {
+        let response = self.get_ok("/blocks/tip/height")?;
+        Ok(response.as_str()?.trim().parse()?)
+    }
+
+    /// Return the fee estimates in sat/vB indexed by confirmation target in blocks
+    pub fn get_fee_estimates(&self) -> anyhow::Result<HashMap<u16, f64>> {
+        let response = self.get_ok("/fee-estimates")?;
+        let estimates: HashMap<String, f64> = serde_json::from_slice(response.as_bytes())?;
+        estimates
+            .into_iter()
+            .map(|(target, fee)| Ok((target.parse()?, fee)))
+            .collect()
+    }
+
+    fn get(&self, path: &str) -> anyhow::Result<minreq::Response> {
+        Ok(minreq::get(format!("{}{}", self.base_url, path))
+            .with_timeout(10)
+            .send()?)
+    }
+
+    fn get_ok(&self, path: &str) -> anyhow::Result<minreq::Response> {
+        let response = self.get(path)?;
+        if response.status_code != 200 {
+            return Err(self.status_error(&response).into());
+        }
+        Ok(response)
+    }
+
+    fn status_error(&self, response: &minreq::Response) -> Error {
+        Error::EsploraStatus {
+            url: response.url.clone(),
+            status_code: response.status_code,
+        }
+    }
+}
+
+impl ElectrsD {
+    /// Return a client of the esplora endpoint, `None` if [crate::Conf::http_enabled] is `false`
+    pub fn esplora_client(&self) -> Option<EsploraClient> {
+        self.esplora_url().map(EsploraClient::new)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::EsploraClient;
+
+    #[test]
+    fn test_base_url() {
+        let client = EsploraClient::new("127.0.0.1:3002");
+        assert_eq!(client.base_url(), "http://127.0.0.1:3002");
+        let client = EsploraClient::new("https://esplora.example");
+        assert_eq!(client.base_url(), "https://esplora.example");
+        let client = EsploraClient::new("httpbin:80");
+        assert_eq!(client.base_url(), "http://httpbin:80");
+    }
+
+    // only esplora exposes the http endpoint
+    #[cfg(any(feature = "esplora_a33e97e1", feature = "legacy"))]
+    #[test]
+    fn test_esplora_client() {
+        use crate::test::setup_nodes;
+        use crate::ElectrsD;
+        use bitcoind::bitcoincore_rpc::RpcApi;
+        use electrum_client::bitcoin::Amount;
+
+        let (electrs_exe, bitcoind, electrsd) = setup_nodes();
+        assert!(electrsd.esplora_client().is_none());
+        assert!(!electrsd.esplora_enabled());
+
+        let conf = crate::Conf {
+            http_enabled: true,
+            ..Default::default()
+        };
+        let electrsd = ElectrsD::with_conf(&electrs_exe, &bitcoind, &conf).unwrap();
+        assert!(electrsd.is_esplora_ready());
+        assert!(electrsd.esplora_enabled());
+        assert_eq!(electrsd.esplora_url(), electrsd.esplora_url.as_deref());
+        let esplora = electrsd.esplora_client().unwrap();
+
+        let height = electrsd.generate(&bitcoind, 101).unwrap();
+        let address = bitcoind
+            .client
+            .get_new_address(None, None)
+            .unwrap()
+            .assume_checked();
+        let txid = bitcoind
+            .client
+            .send_to_address(
+                &address,
+                Amount::from_sat(10_000),
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+            )
+            .unwrap();
+        electrsd.wait_tx(&txid);
+
+        assert_eq!(esplora.get_block_height().unwrap(), height);
+        assert_eq!(esplora.get_tx(&txid).unwrap().unwrap().compute_txid(), txid);
+        esplora.get_fee_estimates().unwrap();
+    }
+}
@@ -1,60 +1,435 @@
 //! Extra functions for the electrs rpc
 //!
 
+use std::ffi::OsStr;
+use std::path::Path;
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
-use electrum_client::{bitcoin::Txid, ElectrumApi};
+use bitcoind::anyhow;
+use bitcoind::bitcoincore_rpc::jsonrpc::serde_json;
+use bitcoind::bitcoincore_rpc::RpcApi;
+use bitcoind::BitcoinD;
+use electrum_client::bitcoin::{block::Header, Script, ScriptBuf, Txid};
+use electrum_client::raw_client::{ElectrumPlaintextStream, RawClient};
+use electrum_client::{
+    ElectrumApi, Param, ScriptHash, ScriptStatus, ServerFeaturesRes, ToElectrumScriptHash,
+};
 
-use crate::ElectrsD;
+use crate::{Conf, ElectrsD, Error};
 
 impl ElectrsD {
     #[cfg(not(feature = "electrs_0_8_10"))]
-    /// wait up to a minute the electrum server has indexed up to the given height.
+    /// wait up to [Conf::wait] timeout, a minute by default, the electrum server has indexed up
+    /// to the given height.
+    ///
+    /// Panics if electrs didn't index the height in time, see [ElectrsD::try_wait_height]
     pub fn wait_height(&self, height: usize) {
-        for _ in 0..600 {
+        self.try_wait_height(height).unwrap()
+    }
+
+    #[cfg(not(feature = "electrs_0_8_10"))]
+    /// wait up to [Conf::wait] timeout the electrum server has indexed up to the given height,
+    /// returns [Error::IndexTimeout] if it didn't
+    pub fn try_wait_height(&self, height: usize) -> anyhow::Result<()> {
+        let start = Instant::now();
+        while start.elapsed() < self.wait.timeout {
             match self.client.block_header_raw(height) {
-                Ok(_) => break,
-                Err(_) => thread::sleep(Duration::from_millis(100)),
+                Ok(_) => return Ok(()),
+                Err(_) => thread::sleep(self.wait.interval),
             }
         }
+        Err(Error::IndexTimeout {
+            expected: format!("height {}", height),
+            waited: start.elapsed(),
+        }
+        .into())
     }
 
-    /// wait up to a minute the electrum server has indexed the given transaction
+    /// wait up to [Conf::wait] timeout, a minute by default, the electrum server has indexed the
+    /// given transaction.
+    ///
+    /// Panics if electrs didn't index the transaction in time, see [ElectrsD::try_wait_tx]
     pub fn wait_tx(&self, txid: &Txid) {
-        'main_loop: for _ in 0..600 {
+        self.try_wait_tx(txid).unwrap()
+    }
+
+    /// wait up to [Conf::wait] timeout the electrum server has indexed the given transaction, returns
+    /// [Error::IndexTimeout] if it didn't
+    pub fn try_wait_tx(&self, txid: &Txid) -> anyhow::Result<()> {
+        self.inner_wait_tx(txid, false, false)
+    }
+
+    /// wait up to [Conf::wait] timeout the electrum server has indexed the given transaction,
+    /// triggering electrs sync every second while waiting
+    ///
+    /// Panics if electrs didn't index the transaction in time
+    pub fn wait_tx_with_trigger(&self, txid: &Txid) {
+        self.inner_wait_tx(txid, true, false).unwrap()
+    }
+
+    /// wait up to [Conf::wait] timeout the electrum server has indexed the given unconfirmed
+    /// transaction in its mempool
+    ///
+    /// Panics if electrs didn't index the transaction in time
+    pub fn wait_mempool_tx(&self, txid: &Txid) {
+        self.inner_wait_tx(txid, false, true).unwrap()
+    }
+
+    fn inner_wait_tx(&self, txid: &Txid, trigger: bool, mempool: bool) -> anyhow::Result<()> {
+        let start = Instant::now();
+        let mut next_trigger = start;
+        'main_loop: while start.elapsed() < self.wait.timeout {
+            if trigger && Instant::now() >= next_trigger {
+                let _ = self.trigger();
+                next_trigger = Instant::now() + Duration::from_secs(1);
+            }
             match self.client.transaction_get(txid) {
                 Ok(tx) => {
                     // having the raw tx doesn't mean the scripts has been indexed
                     let txid = tx.compute_txid();
                     if let Some(output) = tx.output.first() {
-                        let history = self
-                            .client
-                            .script_get_history(&output.script_pubkey)
-                            .unwrap();
+                        let history = self.client.script_get_history(&output.script_pubkey)?;
                         for el in history {
-                            if el.tx_hash == txid {
+                            // unconfirmed txs have height 0, or -1 if they have unconfirmed inputs
+                            if el.tx_hash == txid && (!mempool || el.height <= 0) {
                                 // the tx has to be updated atomically, so founding one is enough
-                                return;
+                                return Ok(());
                             }
                         }
                         // the tx output has not been yet found
+                        thread::sleep(self.wait.interval);
                         continue 'main_loop;
                     }
                     // the tx has 0 ouptut, no need to ensure script_pubkey are indexed
-                    return;
+                    return Ok(());
+                }
+                Err(_) => thread::sleep(self.wait.interval),
+            }
+        }
+        Err(Error::IndexTimeout {
+            expected: format!("tx {}", txid),
+            waited: start.elapsed(),
+        }
+        .into())
+    }
+
+    /// wait up to [Conf::wait] timeout the given transaction has at least `n` confirmations
+    /// according to electrs, triggering electrs sync every second while waiting. A transaction in
+    /// the mempool has 0 confirmations, the count is computed again at every poll so a reorg
+    /// dropping the transaction from the chain resets it.
+    ///
+    /// Returns [Error::IndexTimeout] if the confirmations aren't reached in time
+    pub fn wait_tx_confirmations(&self, txid: &Txid, n: usize) -> anyhow::Result<()> {
+        let start = Instant::now();
+        let mut next_trigger = start;
+        while start.elapsed() < self.wait.timeout {
+            if Instant::now() >= next_trigger {
+                let _ = self.trigger();
+                next_trigger = Instant::now() + Duration::from_secs(1);
+            }
+            if let Ok(tx) = self.client.transaction_get(txid) {
+                if n == 0 {
+                    return Ok(());
+                }
+                let tip = self.client.block_headers_subscribe()?.height;
+                if let Some(output) = tx.output.first() {
+                    let history = self.client.script_get_history(&output.script_pubkey)?;
+                    let height = history
+                        .iter()
+                        .find(|el| el.tx_hash == *txid)
+                        .map_or(0, |el| el.height);
+                    if confirmations(tip, height) >= n {
+                        return Ok(());
+                    }
                 }
-                Err(_) => thread::sleep(Duration::from_millis(100)),
             }
+            thread::sleep(self.wait.interval);
+        }
+        Err(Error::IndexTimeout {
+            expected: format!("{} confirmations of tx {}", n, txid),
+            waited: start.elapsed(),
+        }
+        .into())
+    }
+
+    /// wait up to [Conf::wait] timeout electrs computed a non-empty mempool fee histogram, which
+    /// happens asynchronously after the transactions enter the mempool, returns the histogram as
+    /// `(fee rate in sat/vB, vsize)` pairs or [Error::IndexTimeout] if it stayed empty
+    pub fn wait_fee_histogram(&self) -> anyhow::Result<Vec<(f64, u64)>> {
+        let start = Instant::now();
+        while start.elapsed() < self.wait.timeout {
+            let histogram = self.client.raw_call("mempool.get_fee_histogram", vec![])?;
+            let histogram: Vec<(f64, u64)> = serde_json::from_value(histogram)?;
+            if !histogram.is_empty() {
+                return Ok(histogram);
+            }
+            thread::sleep(self.wait.interval);
+        }
+        Err(Error::IndexTimeout {
+            expected: "non-empty fee histogram".to_string(),
+            waited: start.elapsed(),
+        }
+        .into())
+    }
+
+    /// Return the statistics of the index: the indexed height, queried with the electrum RPC,
+    /// and the size on disk of the work directory
+    pub fn index_stats(&self) -> anyhow::Result<IndexStats> {
+        Ok(IndexStats {
+            height: self.client.block_headers_subscribe()?.height,
+            db_size: dir_size(self.workdir_ref())?,
+        })
+    }
+
+    /// Generate `n` blocks on bitcoind to a new address, trigger electrs sync and wait up to
+    /// [Conf::wait] timeout it has indexed the new tip, returns the new tip height
+    pub fn generate(&self, bitcoind: &BitcoinD, n: u64) -> anyhow::Result<usize> {
+        let address = bitcoind
+            .client
+            .get_new_address(None, None)?
+            .assume_checked();
+        bitcoind.client.generate_to_address(n, &address)?;
+        let height = bitcoind.client.get_block_count()? as usize;
+        let start = Instant::now();
+        while start.elapsed() < self.wait.timeout {
+            // on windows electrs isn't triggered but it syncs on its own anyway
+            let _ = self.trigger();
+            if self.client.block_headers_subscribe()?.height >= height {
+                return Ok(height);
+            }
+            thread::sleep(self.wait.interval);
+        }
+        Err(Error::IndexTimeout {
+            expected: format!("height {}", height),
+            waited: start.elapsed(),
+        }
+        .into())
+    }
+
+    /// Return the features advertised by the electrum server, such as the server version,
+    /// the genesis hash and the supported protocol versions
+    pub fn electrum_features(&self) -> anyhow::Result<ServerFeaturesRes> {
+        Ok(self.client.server_features()?)
+    }
+
+    /// wait up to `timeout` that electrs is at most `max_behind` blocks behind the bitcoind tip,
+    /// returns the current number of blocks electrs is behind
+    pub fn wait_until_behind_at_most(
+        &self,
+        bitcoind: &BitcoinD,
+        max_behind: usize,
+        timeout: Duration,
+    ) -> anyhow::Result<usize> {
+        let start = Instant::now();
+        loop {
+            let bitcoind_height = bitcoind.client.get_block_count()? as usize;
+            let electrs_height = self.client.block_headers_subscribe()?.height;
+            let behind = bitcoind_height.saturating_sub(electrs_height);
+            if behind <= max_behind {
+                return Ok(behind);
+            }
+            if start.elapsed() > timeout {
+                return Err(Error::SyncTimeout {
+                    electrs_height,
+                    bitcoind_height,
+                }
+                .into());
+            }
+            // on windows electrs isn't triggered but it syncs on its own anyway
+            let _ = self.trigger();
+            thread::sleep(self.wait.interval);
+        }
+    }
+
+    /// wait up to [Conf::wait] timeout that electrs indexed up to the current bitcoind tip,
+    /// returns [Error::SyncTimeout] with both heights otherwise
+    pub fn wait_synced(&self, bitcoind: &BitcoinD) -> anyhow::Result<()> {
+        self.wait_until_behind_at_most(bitcoind, 0, self.wait.timeout)?;
+        Ok(())
+    }
+
+    /// Return true if the esplora endpoint answers a `GET /blocks/tip/height` request, false if it
+    /// doesn't or if esplora isn't enabled. Doesn't wait nor retry.
+    pub fn is_esplora_ready(&self) -> bool {
+        self.esplora_url.as_deref().is_some_and(esplora_ready)
+    }
+
+    /// Return the banner of the electrum server, queried with the `server.banner` RPC
+    pub fn server_banner(&self) -> anyhow::Result<String> {
+        let banner = self.client.raw_call("server.banner", vec![])?;
+        banner
+            .as_str()
+            .map(ToString::to_string)
+            .ok_or_else(|| electrum_client::Error::InvalidResponse(banner).into())
+    }
+
+    /// Return the software version of the running electrum server, like `electrs/0.9.11`,
+    /// queried with the `server.version` RPC
+    pub fn server_version(&self) -> anyhow::Result<String> {
+        let params = vec![
+            Param::String(format!("electrsd/{}", env!("CARGO_PKG_VERSION"))),
+            Param::String("1.4".to_string()),
+        ];
+        let response = self.client.raw_call("server.version", params)?;
+        response
+            .get(0)
+            .and_then(|version| version.as_str())
+            .map(ToString::to_string)
+            .ok_or_else(|| electrum_client::Error::InvalidResponse(response).into())
+    }
+
+    /// Check once, without waiting, that the electrs tip is the bitcoind tip, returns
+    /// [Error::NotSynced] with both tips otherwise
+    pub fn assert_synced_to(&self, bitcoind: &BitcoinD) -> anyhow::Result<()> {
+        let header = self.client.block_headers_subscribe()?;
+        let electrs_hash = header.header.block_hash();
+        let bitcoind_hash = bitcoind.client.get_best_block_hash()?;
+        if electrs_hash != bitcoind_hash {
+            let bitcoind_height = bitcoind.client.get_block_count()? as usize;
+            return Err(Error::NotSynced {
+                electrs_height: header.height,
+                electrs_hash,
+                bitcoind_height,
+                bitcoind_hash,
+            }
+            .into());
+        }
+        Ok(())
+    }
+
+    /// Return the block header at the given height
+    pub fn block_header(&self, height: usize) -> anyhow::Result<Header> {
+        Ok(self.client.block_header(height)?)
+    }
+
+    /// Launch electrs against an already populated bitcoind and measure the time it takes to
+    /// index up to the bitcoind tip, useful to benchmark different electrs versions.
+    ///
+    /// Waits up to [Conf::wait] timeout for the sync, returns [Error::SyncTimeout] otherwise
+    pub fn time_full_sync<S: AsRef<OsStr>>(
+        exe: S,
+        bitcoind: &BitcoinD,
+        conf: &Conf,
+    ) -> anyhow::Result<(ElectrsD, Duration)> {
+        let start = Instant::now();
+        let electrsd = ElectrsD::with_conf(exe, bitcoind, conf)?;
+        electrsd.wait_synced(bitcoind)?;
+        Ok((electrsd, start.elapsed()))
+    }
+
+    /// Subscribe to the status changes of the given script, the subscription is dropped together
+    /// with the returned [ScriptSubscription]
+    pub fn subscribe_scripthash(&self, script: &Script) -> anyhow::Result<ScriptSubscription<'_>> {
+        let initial_status = self.client.script_subscribe(script)?;
+        Ok(ScriptSubscription {
+            client: &self.client,
+            script: script.to_owned(),
+            initial_status,
+            interval: self.wait.interval,
+        })
+    }
+}
+
+/// Return true if the esplora endpoint at `esplora_url` answers a `GET /blocks/tip/height` request
+pub(crate) fn esplora_ready(esplora_url: &str) -> bool {
+    minreq::get(format!("http://{}/blocks/tip/height", esplora_url))
+        .with_timeout(1)
+        .send()
+        .is_ok_and(|response| response.status_code == 200)
+}
+
+/// Statistics of the electrs index, returned by [ElectrsD::index_stats]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct IndexStats {
+    /// Height of the tip indexed by electrs
+    pub height: usize,
+    /// Size in bytes of the files in the electrs work directory, mostly the database
+    pub db_size: u64,
+}
+
+/// Number of confirmations of a transaction at the electrum history `height`, 0 for the
+/// unconfirmed ones which have height 0, or -1 with unconfirmed inputs
+fn confirmations(tip: usize, height: i32) -> usize {
+    if height <= 0 {
+        0
+    } else {
+        (tip + 1).saturating_sub(height as usize)
+    }
+}
+
+/// Total size of the files in `dir` and its subdirectories
+fn dir_size(dir: &Path) -> std::io::Result<u64> {
+    let mut size = 0;
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        size += if metadata.is_dir() {
+            dir_size(&entry.path())?
+        } else {
+            metadata.len()
+        };
+    }
+    Ok(size)
+}
+
+/// Return the electrum scripthash of the given script, the reversed sha256 of the script used
+/// by the electrum protocol to identify scripts
+pub fn scripthash(script: &Script) -> ScriptHash {
+    script.to_electrum_scripthash()
+}
+
+/// An electrum subscription to a script, created with [ElectrsD::subscribe_scripthash]
+pub struct ScriptSubscription<'a> {
+    client: &'a RawClient<ElectrumPlaintextStream>,
+    script: ScriptBuf,
+    initial_status: Option<ScriptStatus>,
+    /// Poll interval of [ScriptSubscription::next_status], see [Conf::wait]
+    interval: Duration,
+}
+
+impl ScriptSubscription<'_> {
+    /// The status of the script at the time of the subscription, `None` if the script has no history
+    pub fn initial_status(&self) -> Option<ScriptStatus> {
+        self.initial_status
+    }
+
+    /// wait up to `timeout` for a status update pushed by the server, returns `None` if no update
+    /// is received in time
+    pub fn next_status(&self, timeout: Duration) -> anyhow::Result<Option<ScriptStatus>> {
+        let start = Instant::now();
+        loop {
+            // notifications are read from the socket only while waiting for a response
+            self.client.ping()?;
+            if let Some(status) = self.client.script_pop(&self.script)? {
+                return Ok(Some(status));
+            }
+            if start.elapsed() > timeout {
+                return Ok(None);
+            }
+            thread::sleep(self.interval);
         }
     }
 }
 
+impl Drop for ScriptSubscription<'_> {
+    fn drop(&mut self) {
+        let _ = self.client.script_unsubscribe(&self.script);
+    }
+}
+
 #[cfg(test)]
 mod test {
+    use crate::ext::{confirmations, dir_size, scripthash};
     use crate::test::setup_nodes;
+    use crate::{Conf, ElectrsD, Error, WaitConfig};
     use bitcoind::bitcoincore_rpc::RpcApi;
+    use electrum_client::bitcoin::hashes::Hash;
+    use electrum_client::bitcoin::hex::FromHex;
+    use electrum_client::bitcoin::ScriptBuf;
     use electrum_client::{bitcoin::Amount, ElectrumApi};
+    use std::time::Duration;
 
     #[cfg(not(feature = "electrs_0_8_10"))]
     #[test]
@@ -73,6 +448,123 @@ mod test {
         assert_eq!(header.height, 101);
     }
 
+    #[cfg(not(feature = "electrs_0_8_10"))]
+    #[test]
+    fn test_try_wait_height_timeout() {
+        let (_, _bitcoind, mut electrsd) = setup_nodes();
+        electrsd.set_wait_config(WaitConfig {
+            interval: Duration::from_millis(10),
+            timeout: Duration::from_millis(100),
+        });
+        let err = electrsd.try_wait_height(1000).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<Error>(),
+            Some(Error::IndexTimeout { .. })
+        ));
+    }
+
+    #[cfg(not(feature = "electrs_0_8_10"))]
+    #[test]
+    fn test_generate() {
+        let (_, bitcoind, electrsd) = setup_nodes();
+        assert_eq!(electrsd.generate(&bitcoind, 50).unwrap(), 51);
+        let address = bitcoind
+            .client
+            .get_new_address(None, None)
+            .unwrap()
+            .assume_checked();
+        bitcoind.client.generate_to_address(50, &address).unwrap();
+        electrsd.wait_synced(&bitcoind).unwrap();
+        electrsd.assert_synced_to(&bitcoind).unwrap();
+    }
+
+    #[test]
+    fn test_scripthash() {
+        // example from the electrum protocol documentation
+        let script =
+            ScriptBuf::from_hex("76a91462e907b15cbf27d5425399ebf6f0fb50ebb88f1888ac").unwrap();
+        let expected = <[u8; 32]>::from_hex(
+            "8b01df4e368ea28f8dc0423bcf7a4923e3a12d307c875e47a0cfbf90b5c39161",
+        )
+        .unwrap();
+        assert_eq!(*scripthash(&script), expected);
+    }
+
+    #[test]
+    fn test_electrum_features() {
+        let (_, bitcoind, electrsd) = setup_nodes();
+        let features = electrsd.electrum_features().unwrap();
+        let genesis_header = electrsd.block_header(0).unwrap();
+        assert_eq!(
+            genesis_header.block_hash(),
+            bitcoind.client.get_block_hash(0).unwrap()
+        );
+        let mut genesis_hash = bitcoind.client.get_block_hash(0).unwrap().to_byte_array();
+        genesis_hash.reverse();
+        assert_eq!(features.genesis_hash, genesis_hash);
+        assert!(!features.server_version.is_empty());
+        assert_eq!(electrsd.server_version().unwrap(), features.server_version);
+        electrsd.server_banner().unwrap();
+    }
+
+    #[test]
+    fn test_time_full_sync() {
+        let (electrs_exe, bitcoind, electrsd) = setup_nodes();
+        let address = bitcoind
+            .client
+            .get_new_address(None, None)
+            .unwrap()
+            .assume_checked();
+        bitcoind.client.generate_to_address(100, &address).unwrap();
+        drop(electrsd);
+
+        let (electrsd, _elapsed) =
+            ElectrsD::time_full_sync(&electrs_exe, &bitcoind, &Conf::default()).unwrap();
+        let header = electrsd.client.block_headers_subscribe().unwrap();
+        assert_eq!(header.height, 101);
+    }
+
+    #[test]
+    fn test_subscribe_scripthash() {
+        let (_, bitcoind, electrsd) = setup_nodes();
+        let generate_address = bitcoind
+            .client
+            .get_new_address(None, None)
+            .unwrap()
+            .assume_checked();
+        bitcoind
+            .client
+            .generate_to_address(100, &generate_address)
+            .unwrap();
+        let address = bitcoind
+            .client
+            .get_new_address(None, None)
+            .unwrap()
+            .assume_checked();
+
+        let subscription = electrsd
+            .subscribe_scripthash(&address.script_pubkey())
+            .unwrap();
+        assert!(subscription.initial_status().is_none());
+        bitcoind
+            .client
+            .send_to_address(
+                &address,
+                Amount::from_sat(10000),
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+            )
+            .unwrap();
+        #[cfg(not(target_os = "windows"))]
+        electrsd.trigger().unwrap();
+        let status = subscription.next_status(Duration::from_secs(60)).unwrap();
+        assert!(status.is_some());
+    }
+
     #[test]
     fn test_wait_tx() {
         let (_, bitcoind, electrsd) = setup_nodes();
@@ -114,4 +606,168 @@ mod test {
             .unwrap();
         assert_eq!(history.len(), 1);
     }
+
+    #[test]
+    fn test_wait_mempool_tx() {
+        let (_, bitcoind, electrsd) = setup_nodes();
+        electrsd.generate(&bitcoind, 101).unwrap();
+        let address = bitcoind
+            .client
+            .get_new_address(None, None)
+            .unwrap()
+            .assume_checked();
+        let txid = bitcoind
+            .client
+            .send_to_address(
+                &address,
+                Amount::from_sat(10000),
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+            )
+            .unwrap();
+
+        electrsd.wait_mempool_tx(&txid);
+        let history = electrsd
+            .client
+            .script_get_history(&address.script_pubkey())
+            .unwrap();
+        assert_eq!(history.len(), 1);
+        // unconfirmed
+        assert_eq!(history[0].height, 0);
+    }
+
+    #[test]
+    fn test_wait_tx_with_trigger() {
+        let (_, bitcoind, electrsd) = setup_nodes();
+        electrsd.generate(&bitcoind, 101).unwrap();
+        let address = bitcoind
+            .client
+            .get_new_address(None, None)
+            .unwrap()
+            .assume_checked();
+        let txid = bitcoind
+            .client
+            .send_to_address(
+                &address,
+                Amount::from_sat(10000),
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+            )
+            .unwrap();
+
+        // no explicit trigger, the wait sends it
+        electrsd.wait_tx_with_trigger(&txid);
+        let history = electrsd
+            .client
+            .script_get_history(&address.script_pubkey())
+            .unwrap();
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].tx_hash, txid);
+    }
+
+    #[test]
+    fn test_wait_fee_histogram() {
+        let (_, bitcoind, electrsd) = setup_nodes();
+        electrsd.generate(&bitcoind, 101).unwrap();
+        let address = bitcoind
+            .client
+            .get_new_address(None, None)
+            .unwrap()
+            .assume_checked();
+        let txid = bitcoind
+            .client
+            .send_to_address(
+                &address,
+                Amount::from_sat(10000),
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+            )
+            .unwrap();
+        electrsd.wait_mempool_tx(&txid);
+
+        let histogram = electrsd.wait_fee_histogram().unwrap();
+        assert!(histogram
+            .iter()
+            .all(|(fee_rate, vsize)| *fee_rate > 0.0 && *vsize > 0));
+    }
+
+    #[test]
+    fn test_dir_size() {
+        let dir = bitcoind::tempfile::tempdir().unwrap();
+        assert_eq!(dir_size(dir.path()).unwrap(), 0);
+        std::fs::write(dir.path().join("a"), [0u8; 10]).unwrap();
+        std::fs::create_dir(dir.path().join("sub")).unwrap();
+        std::fs::write(dir.path().join("sub").join("b"), [0u8; 5]).unwrap();
+        assert_eq!(dir_size(dir.path()).unwrap(), 15);
+    }
+
+    #[test]
+    fn test_index_stats() {
+        let (_, bitcoind, electrsd) = setup_nodes();
+        let height = electrsd.generate(&bitcoind, 10).unwrap();
+        let stats = electrsd.index_stats().unwrap();
+        assert_eq!(stats.height, height);
+        assert!(stats.db_size > 0);
+    }
+
+    #[test]
+    fn test_confirmations() {
+        assert_eq!(confirmations(100, -1), 0);
+        assert_eq!(confirmations(100, 0), 0);
+        assert_eq!(confirmations(100, 100), 1);
+        assert_eq!(confirmations(100, 95), 6);
+        // the tx height is above the tip known by electrs
+        assert_eq!(confirmations(100, 101), 0);
+    }
+
+    #[test]
+    fn test_wait_tx_confirmations() {
+        let (_, bitcoind, mut electrsd) = setup_nodes();
+        electrsd.generate(&bitcoind, 101).unwrap();
+        let address = bitcoind
+            .client
+            .get_new_address(None, None)
+            .unwrap()
+            .assume_checked();
+        let txid = bitcoind
+            .client
+            .send_to_address(
+                &address,
+                Amount::from_sat(10000),
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+            )
+            .unwrap();
+        electrsd.wait_tx_confirmations(&txid, 0).unwrap();
+
+        electrsd.set_wait_config(WaitConfig {
+            interval: Duration::from_millis(100),
+            timeout: Duration::from_secs(2),
+        });
+        let err = electrsd.wait_tx_confirmations(&txid, 1).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<Error>(),
+            Some(Error::IndexTimeout { .. })
+        ));
+
+        electrsd.set_wait_config(WaitConfig::default());
+        electrsd.generate(&bitcoind, 6).unwrap();
+        electrsd.wait_tx_confirmations(&txid, 6).unwrap();
+    }
 }
@@ -6,8 +6,16 @@
 //! Utility to run a regtest electrsd process, useful in integration testing environment
 //!
 
+mod builder;
+mod capture;
+#[cfg(feature = "serde")]
+mod conf_file;
 mod error;
+#[cfg(feature = "esplora")]
+mod esplora;
 mod ext;
+#[cfg(feature = "async")]
+mod sleep;
 mod versions;
 
 use bitcoind::anyhow;
@@ -16,20 +24,37 @@ use bitcoind::bitcoincore_rpc::jsonrpc::serde_json::Value;
 use bitcoind::bitcoincore_rpc::RpcApi;
 use bitcoind::tempfile::TempDir;
 use bitcoind::{get_available_port, BitcoinD};
+use electrum_client::bitcoin::constants::genesis_block;
+use electrum_client::bitcoin::Network;
 use electrum_client::raw_client::{ElectrumPlaintextStream, RawClient};
+use electrum_client::ElectrumApi;
 use log::{debug, error, warn};
 use std::env;
 use std::ffi::OsStr;
-use std::path::PathBuf;
-use std::process::{Child, Command, Stdio};
-use std::time::Duration;
+use std::future::{Future, Ready};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpListener};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::task::{Poll, Wake};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
 
 // re-export bitcoind
 pub use bitcoind;
 // re-export electrum_client because calling RawClient methods requires the ElectrumApi trait
 pub use electrum_client;
 
+pub use builder::ConfBuilder;
+use capture::StderrCapture;
+#[cfg(feature = "serde")]
+pub use conf_file::ConfFile;
 pub use error::Error;
+#[cfg(feature = "esplora")]
+pub use esplora::EsploraClient;
+use ext::esplora_ready;
+pub use ext::{scripthash, IndexStats, ScriptSubscription};
 pub use which;
 
 /// Electrs configuration parameters, implements a convenient [Default] for most common use.
@@ -38,28 +63,125 @@ pub use which;
 /// ```
 /// let mut conf = electrsd::Conf::default();
 /// conf.view_stderr = false;
+/// conf.verbosity = if cfg!(feature = "electrs_0_9_1")
+///     || cfg!(feature = "electrs_0_8_10")
+///     || cfg!(feature = "esplora_a33e97e1")
+///     || cfg!(feature = "legacy")
+/// {
+///     3
+/// } else {
+///     0
+/// };
+/// conf.capture_stderr = false;
+/// conf.stderr_buffer_lines = Some(1000);
 /// conf.http_enabled = false;
+/// conf.monitoring = true;
 /// conf.network = "regtest";
+/// conf.allow_unknown_network = false;
+/// conf.skip_ibd_check = false;
 /// conf.tmpdir = None;
 /// conf.staticdir = None;
+/// conf.reindex = false;
+/// conf.db_parallelism = None;
+/// conf.precache_scripts = None;
+/// conf.daemon_dir = None;
+/// conf.config_file = None;
+/// conf.index_batch_size = None;
+/// conf.index_lookup_limit = None;
+/// conf.jsonrpc_timeout = None;
+/// conf.server_banner = None;
+/// conf.env = vec![];
+/// conf.spawn_hook = None;
+/// conf.process_group = false;
+/// conf.listen_addr = std::net::Ipv4Addr::LOCALHOST.into();
+/// conf.connect_host = None;
+/// conf.electrum_port = None;
+/// conf.esplora_port = None;
+/// conf.auth = electrsd::Auth::Cookie;
+/// conf.electrs_ready_timeout = std::time::Duration::from_secs(60);
+/// conf.startup_timeout = Some(std::time::Duration::from_secs(60));
+/// conf.kill_timeout = std::time::Duration::from_secs(10);
+/// conf.post_start = vec![];
+/// conf.verify_genesis = false;
+/// conf.wait = electrsd::WaitConfig::default();
+/// conf.attempts = 3;
 /// assert_eq!(conf, electrsd::Conf::default());
 /// ```
+///
+/// With the `serde` feature the string fields are borrowed from the input, thus only formats
+/// handing out borrowed strings, like `serde_json::from_str` without escape sequences, can
+/// deserialize it. Config files, eg. in TOML, should be deserialized into the owned `ConfFile`
+/// and converted with `Conf::from(&conf_file)`.
 #[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
 #[non_exhaustive]
 pub struct Conf<'a> {
     /// Electrsd command line arguments
-    /// note that `db-dir`, `cookie`, `cookie-file`, `daemon-rpc-addr`, `jsonrpc-import`, `electrum-rpc-addr`, `monitoring-addr`, `http-addr`  cannot be used cause they are automatically initialized.
+    /// note that `db-dir`, `cookie`, `cookie-file`, `auth`, `daemon-rpc-addr`, `daemon-p2p-addr`, `jsonrpc-import`, `electrum-rpc-addr`, `monitoring-addr`, `http-addr`, `network` cannot be used cause they are automatically initialized,
+    /// [ElectrsD::with_conf] returns [Error::ReservedFlag] if they are present.
+    #[cfg_attr(feature = "serde", serde(borrow))]
     pub args: Vec<&'a str>,
 
     /// if `true` electrsd log output will not be suppressed
     pub view_stderr: bool,
 
-    /// if `true` electrsd exposes an esplora endpoint
+    /// electrs log verbosity from 0 to 4, emitted as the corresponding number of `-v` flags on
+    /// versions supporting them, or as `--log-filters` with levels `WARN`, `INFO`, `DEBUG` and
+    /// `TRACE` on newer ones, where 0 keeps the electrs default. Values above 4 are capped.
+    pub verbosity: u8,
+
+    /// if `true` electrsd log output is captured and available through [ElectrsD::logs], it's
+    /// also included in [Error::EarlyExit]. If `view_stderr` is also `true` the output is still
+    /// shown.
+    pub capture_stderr: bool,
+
+    /// Maximum number of lines kept when `capture_stderr` is `true`, only the most recent lines
+    /// are kept. `None` keeps everything.
+    pub stderr_buffer_lines: Option<usize>,
+
+    /// if `true` electrsd exposes an esplora endpoint, [ElectrsD::with_conf] returns once it
+    /// answers requests
     pub http_enabled: bool,
 
-    /// Must match bitcoind network
+    /// if `true` electrs exposes the monitoring endpoint on a newly allocated port, see
+    /// [ElectrsD::monitoring_url].
+    ///
+    /// When `false` no port is allocated by electrsd, electrs is given port 0 to bind the endpoint
+    /// on a free port picked by the OS, which isn't exposed.
+    pub monitoring: bool,
+
+    /// Must match bitcoind network, [ElectrsD::with_conf] returns [Error::NetworkMismatch]
+    /// otherwise
     pub network: &'a str,
 
+    /// if `true` `network` isn't checked against the bitcoind chain, useful with custom networks
+    pub allow_unknown_network: bool,
+
+    /// if `true` [ElectrsD::with_conf] doesn't generate a block when bitcoind is in initial block
+    /// download, so that the chain isn't modified. Note electrs doesn't index anything until
+    /// bitcoind leaves the initial block download.
+    pub skip_ibd_check: bool,
+
+    /// Address electrs binds the electrum, monitoring and esplora endpoints to, defaults to the
+    /// loopback so that they aren't exposed on every interface
+    pub listen_addr: IpAddr,
+
+    /// Host used in [ElectrsD::electrum_url] and [ElectrsD::esplora_url] to connect to electrs,
+    /// when `None` it's `listen_addr`, or the loopback if `listen_addr` is unspecified (eg. `0.0.0.0`)
+    pub connect_host: Option<&'a str>,
+
+    /// Fixed port of the electrum protocol, instead of one chosen by the OS. [ElectrsD::with_conf]
+    /// returns [Error::PortInUse] if it's already taken.
+    pub electrum_port: Option<u16>,
+
+    /// Fixed port of the esplora protocol when `http_enabled`, see `electrum_port`
+    pub esplora_port: Option<u16>,
+
+    /// How electrs authenticates to the bitcoind RPC, the bitcoind cookie file by default
+    #[cfg_attr(feature = "serde", serde(borrow))]
+    pub auth: Auth<'a>,
+
     /// Optionally specify a temporary or persistent working directory for the electrs.
     /// electrs index files will be stored in this path.
     /// The following two parameters can be configured to simulate desired working directory configuration.
@@ -75,42 +197,301 @@ pub struct Conf<'a> {
     /// Persistent directory path
     pub staticdir: Option<PathBuf>,
 
-    /// Try to spawn the process `attempt` time
+    /// if `true` the content of `staticdir` is removed before launching electrs, so that it
+    /// builds the index from scratch. No-op with temporary directories.
+    pub reindex: bool,
+
+    /// Number of threads used by RocksDB for flushes and compactions (`--db-parallelism`).
+    ///
+    /// Lower it on memory constrained environments, raise it to speed up initial sync.
+    /// Ignored with a warning on electrs versions not supporting it.
+    pub db_parallelism: Option<usize>,
+
+    /// File with the scripts whose history is cached at startup (`--precache-scripts`), speeding
+    /// up the first queries on them.
+    /// Supported only by esplora, ignored with a warning on other versions.
+    pub precache_scripts: Option<PathBuf>,
+
+    /// bitcoind data directory (`--daemon-dir`), usually [BitcoinD::workdir].
+    ///
+    /// electrs 0.8.10 and esplora read the blocks from its block files instead of importing them
+    /// with the JSON-RPC, which is much faster on big chains.
+    pub daemon_dir: Option<PathBuf>,
+
+    /// electrs config file (`--conf`), the values set automatically by electrsd like `db_dir` or
+    /// `network` take precedence over the ones in the file, a warning is logged for each of them.
+    /// Not supported by esplora, ignored with a warning.
+    pub config_file: Option<PathBuf>,
+
+    /// Number of blocks fetched and indexed in a single batch (`--index-batch-size`).
+    /// Not supported by esplora, ignored with a warning.
+    pub index_batch_size: Option<usize>,
+
+    /// Number of transactions above which a script history lookup is refused
+    /// (`--index-lookup-limit`).
+    /// Not supported by electrs 0.8.10 and esplora, ignored with a warning.
+    pub index_lookup_limit: Option<usize>,
+
+    /// Timeout of the JSON-RPC requests electrs sends to bitcoind, rounded up to seconds, at least
+    /// one (`--jsonrpc-timeout-secs`).
+    /// Supported only by electrs 0.9.11, ignored with a warning on other versions.
+    pub jsonrpc_timeout: Option<Duration>,
+
+    /// Welcome message returned by the electrum `server.banner` RPC (`--server-banner`, or
+    /// `--electrum-banner` on esplora), see [ElectrsD::server_banner]. Owned so that multiline
+    /// values can be deserialized.
+    pub server_banner: Option<String>,
+
+    /// Environment variables set only in the electrs process, like `RUST_LOG`
+    #[cfg_attr(feature = "serde", serde(borrow))]
+    pub env: Vec<(&'a str, &'a str)>,
+
+    /// Callback invoked with the fully built electrs [Command] right before it's spawned, useful
+    /// to add environment variables, redirect IO or log the launch.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub spawn_hook: Option<SpawnHook>,
+
+    /// if `true` electrs is spawned in its own process group on unix, so that it doesn't receive
+    /// the signals meant for the test runner. Then a Ctrl-C in the terminal doesn't reach electrs,
+    /// which keeps running if the test process dies without dropping [ElectrsD]. Ignored on windows.
+    pub process_group: bool,
+
+    /// Maximum time waited for electrs to accept electrum connections after being spawned,
+    /// [ElectrsD::with_conf] returns [Error::StartupTimeout] when exceeded. `None` waits forever.
+    pub startup_timeout: Option<Duration>,
+
+    /// Maximum time [ElectrsD::kill] waits for electrs to exit after `SIGINT`, then it's killed
+    /// with `SIGKILL` and [Error::KillTimeout] is returned
+    pub kill_timeout: Duration,
+
+    /// Maximum time [ElectrsD::with_conf_synced] waits for electrs to index up to the bitcoind tip
+    pub electrs_ready_timeout: Duration,
+
+    /// bitcoind RPC calls, as method name and params, executed in order right after electrs
+    /// started, useful for common setup like loading a wallet or generating blocks
+    #[cfg_attr(feature = "serde", serde(borrow))]
+    pub post_start: Vec<(&'a str, Vec<Value>)>,
+
+    /// if `true` the genesis block returned by electrs is checked against the one of `network`
+    /// right after launch, returning [Error::GenesisMismatch] if they differ
+    pub verify_genesis: bool,
+
+    /// Poll interval and timeout of the wait helpers like [ElectrsD::wait_height] and
+    /// [ElectrsD::wait_tx], defaults to polling every 100ms for up to a minute.
+    /// It can be changed after launch with [ElectrsD::set_wait_config]
+    pub wait: WaitConfig,
+
+    /// Try to spawn the process again up to `attempts` times when it exits early, 0 returns
+    /// [Error::EarlyExit] at the first failure
     ///
     /// The OS is giving available ports to use, however, they aren't booked, so it could rarely
     /// happen they are used at the time the process is spawn. When retrying other available ports
     /// are returned reducing the probability of conflicts to negligible.
-    attempts: u8,
+    pub attempts: u8,
 }
 
 impl Default for Conf<'_> {
     fn default() -> Self {
-        let args = if cfg!(feature = "electrs_0_9_1")
+        let verbosity = if cfg!(feature = "electrs_0_9_1")
             || cfg!(feature = "electrs_0_8_10")
             || cfg!(feature = "esplora_a33e97e1")
             || cfg!(feature = "legacy")
         {
-            vec!["-vvv"]
+            3
         } else {
-            vec![]
+            0
         };
 
         Conf {
-            args,
+            args: vec![],
             view_stderr: false,
+            verbosity,
+            capture_stderr: false,
+            stderr_buffer_lines: Some(1000),
             http_enabled: false,
+            monitoring: true,
             network: "regtest",
+            allow_unknown_network: false,
+            skip_ibd_check: false,
+            listen_addr: Ipv4Addr::LOCALHOST.into(),
+            connect_host: None,
+            electrum_port: None,
+            esplora_port: None,
+            auth: Auth::Cookie,
             tmpdir: None,
             staticdir: None,
+            reindex: false,
+            db_parallelism: None,
+            precache_scripts: None,
+            daemon_dir: None,
+            config_file: None,
+            index_batch_size: None,
+            index_lookup_limit: None,
+            jsonrpc_timeout: None,
+            server_banner: None,
+            env: vec![],
+            spawn_hook: None,
+            process_group: false,
+            startup_timeout: Some(Duration::from_secs(60)),
+            kill_timeout: Duration::from_secs(10),
+            electrs_ready_timeout: Duration::from_secs(60),
+            post_start: vec![],
+            verify_genesis: false,
+            wait: WaitConfig::default(),
             attempts: 3,
         }
     }
 }
 
+impl Conf<'static> {
+    /// Create a configuration from the environment variables below, falling back to the
+    /// [Default] values for the ones not set:
+    ///
+    /// * `ELECTRSD_VIEW_STDERR`: [Conf::view_stderr], `1` or `true` to enable
+    /// * `ELECTRSD_HTTP_ENABLED`: [Conf::http_enabled], `1` or `true` to enable
+    /// * `ELECTRSD_NETWORK`: [Conf::network], one of `bitcoin`, `testnet`, `testnet4`, `regtest` or
+    ///   `signet`, other values are ignored
+    /// * `ELECTRSD_STATICDIR`: [Conf::staticdir]
+    /// * `ELECTRSD_VERBOSITY`: [Conf::verbosity]
+    pub fn from_env() -> Conf<'static> {
+        let mut conf = Conf::default();
+        if let Some(view_stderr) = env_flag("ELECTRSD_VIEW_STDERR") {
+            conf.view_stderr = view_stderr;
+        }
+        if let Some(http_enabled) = env_flag("ELECTRSD_HTTP_ENABLED") {
+            conf.http_enabled = http_enabled;
+        }
+        if let Ok(network) = env::var("ELECTRSD_NETWORK") {
+            match static_network(&network) {
+                Some(network) => conf.network = network,
+                None => warn!("unknown ELECTRSD_NETWORK {}, ignoring it", network),
+            }
+        }
+        if let Some(staticdir) = env::var_os("ELECTRSD_STATICDIR") {
+            conf.staticdir = Some(staticdir.into());
+        }
+        if let Ok(verbosity) = env::var("ELECTRSD_VERBOSITY") {
+            match verbosity.parse() {
+                Ok(verbosity) => conf.verbosity = verbosity,
+                Err(_) => warn!("invalid ELECTRSD_VERBOSITY {}, ignoring it", verbosity),
+            }
+        }
+        conf
+    }
+}
+
+/// Returns the electrs arguments setting the log verbosity, see [Conf::verbosity]
+fn verbosity_args(verbosity: u8) -> Vec<&'static str> {
+    let verbosity = verbosity.min(4) as usize;
+    if verbosity == 0 {
+        vec![]
+    } else if cfg!(feature = "electrs_0_9_1")
+        || cfg!(feature = "electrs_0_8_10")
+        || cfg!(feature = "esplora_a33e97e1")
+        || cfg!(feature = "legacy")
+    {
+        vec![["-v", "-vv", "-vvv", "-vvvv"][verbosity - 1]]
+    } else {
+        vec![
+            "--log-filters",
+            ["WARN", "INFO", "DEBUG", "TRACE"][verbosity - 1],
+        ]
+    }
+}
+
+/// Returns `timeout` in whole seconds, rounded up so that sub-second values don't become 0
+fn timeout_secs(timeout: Duration) -> u64 {
+    let secs = timeout.as_secs() + u64::from(timeout.subsec_nanos() > 0);
+    secs.max(1)
+}
+
+/// Returns the `'static` name of a known electrs network, `None` if unknown
+fn static_network(network: &str) -> Option<&'static str> {
+    ["bitcoin", "testnet", "testnet4", "regtest", "signet"]
+        .iter()
+        .find(|known| **known == network)
+        .copied()
+}
+
+/// Returns the value of a boolean env var, `None` if it isn't set
+fn env_flag(name: &str) -> Option<bool> {
+    env::var(name)
+        .ok()
+        .map(|value| value == "1" || value.eq_ignore_ascii_case("true"))
+}
+
+/// Authentication used by electrs to connect to the bitcoind RPC, see [Conf::auth]
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Auth<'a> {
+    /// Use the cookie file of the given bitcoind, passed with `--cookie-file` or its content with
+    /// `--cookie` on legacy versions.
+    ///
+    /// On legacy versions the cookie is read only at launch, if bitcoind restarts electrs can't
+    /// authenticate anymore, see [ElectrsD::is_cookie_stale]
+    Cookie,
+    /// Use the given `rpcuser` and `rpcpassword`, passed with `--auth` or `--cookie` on legacy
+    /// versions
+    UserPass {
+        /// The bitcoind `rpcuser`
+        user: &'a str,
+        /// The bitcoind `rpcpassword`
+        pass: &'a str,
+    },
+}
+
+/// A callback receiving the electrs [Command] before it's spawned, see [Conf::spawn_hook]
+#[derive(Clone)]
+pub struct SpawnHook(Arc<dyn Fn(&mut Command) + Send + Sync>);
+
+impl SpawnHook {
+    /// Create a new hook from the given callback
+    pub fn new<F: Fn(&mut Command) + Send + Sync + 'static>(hook: F) -> Self {
+        SpawnHook(Arc::new(hook))
+    }
+}
+
+impl std::fmt::Debug for SpawnHook {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "SpawnHook")
+    }
+}
+
+impl PartialEq for SpawnHook {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for SpawnHook {}
+
+/// How the wait helpers poll electrs, see [Conf::wait]
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct WaitConfig {
+    /// Time slept between two checks
+    pub interval: Duration,
+    /// Maximum time waited before returning [Error::IndexTimeout]
+    pub timeout: Duration,
+}
+
+impl Default for WaitConfig {
+    fn default() -> Self {
+        WaitConfig {
+            interval: Duration::from_millis(100),
+            timeout: Duration::from_secs(60),
+        }
+    }
+}
+
 /// Struct representing the electrs process with related information
 pub struct ElectrsD {
     /// Process child handle, used to terminate the process when this struct is dropped
     process: Child,
+    /// Command used to spawn the process, used to restart it
+    command: Command,
+    /// Captured stderr of the process, if enabled
+    stderr_capture: Option<StderrCapture>,
     /// Electrum client connected to the electrs process
     pub client: RawClient<ElectrumPlaintextStream>,
     /// Work directory, where the electrs stores indexes and other stuffs.
@@ -119,6 +500,30 @@ pub struct ElectrsD {
     pub electrum_url: String,
     /// Url to connect to esplora protocol (http)
     pub esplora_url: Option<String>,
+    /// Url of the monitoring endpoint exposing prometheus metrics at `/metrics`, `None` when
+    /// [Conf::monitoring] is disabled
+    pub monitoring_url: Option<String>,
+    /// Port of the electrum protocol
+    electrum_port: u16,
+    /// Port of the esplora protocol, if enabled
+    esplora_port: Option<u16>,
+    /// Port of the monitoring endpoint, if enabled
+    monitoring_port: Option<u16>,
+    /// Address electrs listens to, the loopback if it listens to every interface
+    connect_ip: IpAddr,
+    /// Configuration used to create the electrum client
+    client_config: electrum_client::Config,
+    /// Time elapsed from the launch until electrs accepted electrum connections and, if enabled,
+    /// esplora requests
+    startup_duration: Duration,
+    /// Maximum time waited for electrs to accept connections, see [Conf::startup_timeout]
+    startup_timeout: Option<Duration>,
+    /// Poll interval and timeout of the wait helpers, see [Conf::wait]
+    wait: WaitConfig,
+    /// Maximum time waited for electrs to exit gracefully, see [Conf::kill_timeout]
+    kill_timeout: Duration,
+    /// bitcoind cookie file and the value passed with `--cookie`, only on legacy versions
+    cookie: Option<(PathBuf, String)>,
 }
 
 /// The DataDir struct defining the kind of data directory electrs will use.
@@ -133,9 +538,14 @@ pub enum DataDir {
 impl DataDir {
     /// Return the data directory path
     fn path(&self) -> PathBuf {
+        self.path_ref().to_path_buf()
+    }
+
+    /// Return the data directory path without allocating
+    fn path_ref(&self) -> &Path {
         match self {
-            Self::Persistent(path) => path.to_owned(),
-            Self::Temporary(tmp_dir) => tmp_dir.path().to_path_buf(),
+            Self::Persistent(path) => path,
+            Self::Temporary(tmp_dir) => tmp_dir.path(),
         }
     }
 }
@@ -152,146 +562,170 @@ impl ElectrsD {
         bitcoind: &BitcoinD,
         conf: &Conf,
     ) -> anyhow::Result<ElectrsD> {
-        let response = bitcoind.client.call::<Value>("getblockchaininfo", &[])?;
-        if response
-            .get("initialblockdownload")
-            .and_then(|v| v.as_bool())
-            .unwrap_or(false)
-        {
-            // electrum will remain idle until bitcoind is in IBD
-            // bitcoind will remain in IBD if doesn't see a block from a long time, thus adding a block
-            let node_address = bitcoind.client.call::<Value>("getnewaddress", &[])?;
-            bitcoind
-                .client
-                .call::<Value>("generatetoaddress", &[1.into(), node_address])
-                .unwrap();
-        }
+        let daemon = Daemon::from_bitcoind(bitcoind, conf)?;
+        Self::launch(exe.as_ref(), &daemon, conf)
+    }
 
-        let mut args = conf.args.clone();
+    /// Create a new electrs process using given [Conf] connected to a bitcoind not managed by
+    /// [BitcoinD], like one running in a container, given its RPC and p2p addresses and its cookie
+    /// file.
+    ///
+    /// The cookie file is used also to call bitcoind, unless [Conf::auth] is [Auth::UserPass].
+    /// `p2p_socket` is required by electrs versions fetching blocks through p2p.
+    pub fn with_conf_rpc<S: AsRef<OsStr>>(
+        exe: S,
+        rpc_socket: SocketAddr,
+        p2p_socket: Option<SocketAddr>,
+        cookie_file: &Path,
+        conf: &Conf,
+    ) -> anyhow::Result<ElectrsD> {
+        let daemon = Daemon::from_rpc(rpc_socket, p2p_socket, cookie_file, conf)?;
+        Self::launch(exe.as_ref(), &daemon, conf)
+    }
 
-        let work_dir = match (&conf.tmpdir, &conf.staticdir) {
-            (Some(_), Some(_)) => return Err(Error::BothDirsSpecified.into()),
-            (Some(tmpdir), None) => DataDir::Temporary(TempDir::new_in(tmpdir)?),
-            (None, Some(workdir)) => {
-                std::fs::create_dir_all(workdir)?;
-                DataDir::Persistent(workdir.to_owned())
-            }
-            (None, None) => match env::var("TEMPDIR_ROOT").map(PathBuf::from) {
-                Ok(path) => DataDir::Temporary(TempDir::new_in(path)?),
-                Err(_) => DataDir::Temporary(TempDir::new()?),
-            },
-        };
+    fn launch(exe: &OsStr, daemon: &Daemon, conf: &Conf) -> anyhow::Result<ElectrsD> {
+        block_on(launch_with(exe, daemon, conf, thread_sleep))
+    }
 
-        let db_dir = format!("{}", work_dir.path().display());
-        args.push("--db-dir");
-        args.push(&db_dir);
+    /// Async version of [ElectrsD::with_conf], waiting for electrs to accept connections without
+    /// blocking the thread, so that it can be used in async tests.
+    ///
+    /// It doesn't depend on any async runtime, only the waits between the connection attempts are
+    /// async. The other steps block the thread: the calls to bitcoind, including the generation of
+    /// a block to get it out of the initial block download, the spawn of the process, every
+    /// connection attempt of the electrum client and, when [Conf::http_enabled], every request
+    /// checking the esplora endpoint. They are usually quick since electrs runs locally, run the
+    /// future with a blocking-friendly executor if they must not stall other tasks.
+    #[cfg(feature = "async")]
+    pub async fn with_conf_async<S: AsRef<OsStr>>(
+        exe: S,
+        bitcoind: &BitcoinD,
+        conf: &Conf<'_>,
+    ) -> anyhow::Result<ElectrsD> {
+        let daemon = Daemon::from_bitcoind(bitcoind, conf)?;
+        launch_with(exe.as_ref(), &daemon, conf, sleep::Sleep::new).await
+    }
 
-        args.push("--network");
-        args.push(conf.network);
+    /// Initialize `env_logger` (if not already initialized) and create a new electrs process
+    /// connected with the given bitcoind, showing electrs logs when the debug log level is enabled
+    #[cfg(feature = "test-utils")]
+    pub fn new_with_env_logger_init<S: AsRef<OsStr>>(
+        exe: S,
+        bitcoind: &BitcoinD,
+    ) -> anyhow::Result<ElectrsD> {
+        let _ = env_logger::try_init();
+        let conf = Conf {
+            view_stderr: log::log_enabled!(log::Level::Debug),
+            ..Default::default()
+        };
+        ElectrsD::with_conf(exe, bitcoind, &conf)
+    }
 
-        #[cfg(not(feature = "legacy"))]
-        let cookie_file;
-        #[cfg(not(feature = "legacy"))]
-        {
-            args.push("--cookie-file");
-            cookie_file = format!("{}", bitcoind.params.cookie_file.display());
-            args.push(&cookie_file);
+    /// Create a new electrs process using given [Conf] connected with the given bitcoind, launching
+    /// it again up to `retries` times if it fails.
+    ///
+    /// [Conf::attempts] is ignored, every failure is a retry. Returns [Error::AttemptsExhausted]
+    /// with the last failure if all the attempts failed.
+    pub fn new_with_retries<S: AsRef<OsStr>>(
+        exe: S,
+        bitcoind: &BitcoinD,
+        conf: &Conf,
+        retries: u8,
+    ) -> anyhow::Result<ElectrsD> {
+        let mut conf = conf.clone();
+        conf.attempts = 0;
+        let mut last_error = String::new();
+        for attempt in 0..=retries {
+            match ElectrsD::with_conf(exe.as_ref(), bitcoind, &conf) {
+                Ok(electrsd) => return Ok(electrsd),
+                Err(e) => {
+                    warn!("attempt {} failed with: {:#}", attempt + 1, e);
+                    last_error = format!("{:#}", e);
+                }
+            }
         }
-
-        #[cfg(feature = "legacy")]
-        let mut cookie_value;
-        #[cfg(feature = "legacy")]
-        {
-            use std::io::Read;
-            args.push("--cookie");
-            let mut cookie = std::fs::File::open(&bitcoind.params.cookie_file)?;
-            cookie_value = String::new();
-            cookie.read_to_string(&mut cookie_value)?;
-            args.push(&cookie_value);
+        Err(Error::AttemptsExhausted {
+            attempts: retries as usize + 1,
+            last_error,
         }
+        .into())
+    }
 
-        args.push("--daemon-rpc-addr");
-        let rpc_socket = bitcoind.params.rpc_socket.to_string();
-        args.push(&rpc_socket);
-
-        let p2p_socket;
-        if cfg!(feature = "electrs_0_8_10")
+    /// Launch a new bitcoind with the default configuration, with the p2p port open when the
+    /// electrs version needs it, and an electrs process connected to it using the given [Conf]
+    pub fn with_node<S: AsRef<OsStr>, B: AsRef<OsStr>>(
+        exe: S,
+        bitcoind_exe: B,
+        conf: &Conf,
+    ) -> anyhow::Result<(BitcoinD, ElectrsD)> {
+        let mut bitcoind_conf = bitcoind::Conf::default();
+        if !(cfg!(feature = "electrs_0_8_10")
             || cfg!(feature = "esplora_a33e97e1")
-            || cfg!(feature = "legacy")
+            || cfg!(feature = "legacy"))
         {
-            args.push("--jsonrpc-import");
-        } else {
-            args.push("--daemon-p2p-addr");
-            p2p_socket = bitcoind
-                .params
-                .p2p_socket
-                .expect("electrs_0_9_1 requires bitcoind with p2p port open")
-                .to_string();
-            args.push(&p2p_socket);
+            bitcoind_conf.p2p = bitcoind::P2P::Yes;
         }
+        let bitcoind = BitcoinD::with_conf(bitcoind_exe, &bitcoind_conf)?;
+        let electrsd = ElectrsD::with_conf(exe, &bitcoind, conf)?;
+        Ok((bitcoind, electrsd))
+    }
 
-        let electrum_url = format!("0.0.0.0:{}", get_available_port()?);
-        args.push("--electrum-rpc-addr");
-        args.push(&electrum_url);
-
-        // would be better to disable it, didn't found a flag
-        let monitoring = format!("0.0.0.0:{}", get_available_port()?);
-        args.push("--monitoring-addr");
-        args.push(&monitoring);
-
-        let esplora_url_string;
-        let esplora_url = if conf.http_enabled {
-            esplora_url_string = format!("0.0.0.0:{}", get_available_port()?);
-            args.push("--http-addr");
-            args.push(&esplora_url_string);
-            #[allow(clippy::redundant_clone)]
-            Some(esplora_url_string.clone())
-        } else {
-            None
-        };
+    /// Create a new electrs process using given [Conf] connected with the given bitcoind, and wait
+    /// up to [Conf::electrs_ready_timeout] that electrs indexed up to the bitcoind tip
+    pub fn with_conf_synced<S: AsRef<OsStr>>(
+        exe: S,
+        bitcoind: &BitcoinD,
+        conf: &Conf,
+    ) -> anyhow::Result<ElectrsD> {
+        let electrsd = ElectrsD::with_conf(exe, bitcoind, conf)?;
+        electrsd.wait_until_behind_at_most(bitcoind, 0, conf.electrs_ready_timeout)?;
+        Ok(electrsd)
+    }
 
-        let view_stderr = if conf.view_stderr {
-            Stdio::inherit()
-        } else {
-            Stdio::null()
+    /// Restart the electrs process keeping its work directory, also when temporary, so that the
+    /// index survives, then reconnect [ElectrsD::client].
+    ///
+    /// electrs is launched again with the same arguments, thus listening on the same ports. A
+    /// running electrs is stopped as with [ElectrsD::kill], one that exited is just launched again.
+    /// Returns [Error::StaleCookie] without stopping electrs if the bitcoind cookie passed on the
+    /// command line is stale, see [ElectrsD::is_cookie_stale].
+    pub fn restart(&mut self) -> anyhow::Result<()> {
+        if let Some((cookie_file, _)) = self.cookie.as_ref().filter(|_| self.is_cookie_stale()) {
+            return Err(Error::StaleCookie {
+                cookie_file: cookie_file.clone(),
+            }
+            .into());
+        }
+        self.kill()?;
+        let conf = Conf {
+            startup_timeout: self.startup_timeout,
+            kill_timeout: self.kill_timeout,
+            wait: self.wait,
+            attempts: 0,
+            ..Default::default()
         };
-
-        debug!("args: {:?}", args);
-        let mut process = Command::new(&exe)
-            .args(args)
-            .stderr(view_stderr)
-            .spawn()
-            .with_context(|| format!("Error while executing {:?}", exe.as_ref()))?;
-
-        let client = loop {
-            if let Some(status) = process.try_wait()? {
-                if conf.attempts > 0 {
-                    warn!("early exit with: {:?}. Trying to launch again ({} attempts remaining), maybe some other process used our available port", status, conf.attempts);
-                    let mut conf = conf.clone();
-                    conf.attempts -= 1;
-                    return Self::with_conf(exe, bitcoind, &conf)
-                        .with_context(|| format!("Remaining attempts {}", conf.attempts));
-                } else {
-                    error!("early exit with: {:?}", status);
-                    return Err(Error::EarlyExit(status).into());
-                }
+        let mut launch = Launch::respawn(self)?;
+        let client = match block_on(launch.wait(&conf, &thread_sleep)) {
+            Ok(LaunchState::Connected(client)) => client,
+            Ok(_) => {
+                // the monitoring port has been taken meanwhile, the arguments can't change
+                let port = launch.monitoring_port.unwrap_or_default();
+                launch.restore(self);
+                return Err(Error::PortInUse(port).into());
             }
-            match RawClient::new(&electrum_url, None) {
-                Ok(client) => break client,
-                Err(_) => std::thread::sleep(Duration::from_millis(500)),
+            Err(e) => {
+                launch.restore(self);
+                return Err(e);
             }
         };
-
-        Ok(ElectrsD {
-            process,
-            client,
-            work_dir,
-            electrum_url,
-            esplora_url,
-        })
+        *self = launch.finish(client, &conf);
+        Ok(())
     }
 
     /// triggers electrs sync by sending the `SIGUSR1` signal, useful to call after a block for example
+    ///
+    /// Signals aren't available on windows, where this returns [Error::SignalUnsupported]: electrs
+    /// syncs only when it polls bitcoind on its own, so tests should rely on the `wait_*` methods.
     #[cfg(not(target_os = "windows"))]
     pub fn trigger(&self) -> anyhow::Result<()> {
         Ok(nix::sys::signal::kill(
@@ -300,9 +734,139 @@ impl ElectrsD {
         )?)
     }
 
+    /// triggering electrs sync isn't supported on windows, always returns
+    /// [Error::SignalUnsupported], rely on the `wait_*` methods instead
     #[cfg(target_os = "windows")]
     pub fn trigger(&self) -> anyhow::Result<()> {
-        Ok(())
+        Err(Error::SignalUnsupported.into())
+    }
+
+    /// freezes the electrs process by sending the `SIGSTOP` signal, so that it doesn't index
+    /// anything until [ElectrsD::resume] is called.
+    ///
+    /// Note the electrum client doesn't receive responses while the process is paused.
+    #[cfg(not(target_os = "windows"))]
+    pub fn pause(&self) -> anyhow::Result<()> {
+        Ok(nix::sys::signal::kill(
+            nix::unistd::Pid::from_raw(self.process.id() as i32),
+            nix::sys::signal::SIGSTOP,
+        )?)
+    }
+
+    /// pausing electrs isn't supported on windows, always returns [Error::SignalUnsupported]
+    #[cfg(target_os = "windows")]
+    pub fn pause(&self) -> anyhow::Result<()> {
+        Err(Error::SignalUnsupported.into())
+    }
+
+    /// resumes the electrs process previously frozen with [ElectrsD::pause] by sending the
+    /// `SIGCONT` signal
+    #[cfg(not(target_os = "windows"))]
+    pub fn resume(&self) -> anyhow::Result<()> {
+        Ok(nix::sys::signal::kill(
+            nix::unistd::Pid::from_raw(self.process.id() as i32),
+            nix::sys::signal::SIGCONT,
+        )?)
+    }
+
+    /// resuming electrs isn't supported on windows, always returns [Error::SignalUnsupported]
+    #[cfg(target_os = "windows")]
+    pub fn resume(&self) -> anyhow::Result<()> {
+        Err(Error::SignalUnsupported.into())
+    }
+
+    /// Return the last lines written by electrs on stderr, empty if [Conf::capture_stderr] is
+    /// disabled
+    pub fn logs(&self) -> Vec<String> {
+        stderr_lines(&self.stderr_capture)
+    }
+
+    /// Return the electrs version of the enabled version feature, like `v0.9.11`, `None` if no
+    /// version feature is enabled. See [ElectrsD::server_version] for the one of the running
+    /// process
+    pub fn version(&self) -> Option<&'static str> {
+        enabled_version()
+    }
+
+    /// Return the process id of electrs, it changes after [ElectrsD::restart]
+    pub fn pid(&self) -> u32 {
+        self.process.id()
+    }
+
+    /// Return true if electrs authenticates to bitcoind with a cookie value which is no longer the
+    /// one in the bitcoind cookie file, for example because bitcoind restarted. It can happen only
+    /// on legacy versions, which get the cookie value instead of the cookie file path.
+    pub fn is_cookie_stale(&self) -> bool {
+        self.cookie.as_ref().is_some_and(|(cookie_file, value)| {
+            std::fs::read_to_string(cookie_file).is_ok_and(|current| current != *value)
+        })
+    }
+
+    /// Return true if the electrs process hasn't exited, see [ElectrsD::exit_status] for how it
+    /// exited otherwise
+    pub fn is_running(&mut self) -> bool {
+        matches!(self.process.try_wait(), Ok(None))
+    }
+
+    /// Return the exit status of electrs, `None` if it's still running
+    pub fn exit_status(&mut self) -> anyhow::Result<Option<ExitStatus>> {
+        Ok(self.process.try_wait()?)
+    }
+
+    /// Return the port of the electrum protocol
+    pub fn electrum_port(&self) -> u16 {
+        self.electrum_port
+    }
+
+    /// Return the port of the esplora protocol, if enabled
+    pub fn esplora_port(&self) -> Option<u16> {
+        self.esplora_port
+    }
+
+    /// Return the socket address to connect to the electrum protocol, useful with other electrum
+    /// clients. The ip is [Conf::listen_addr], or the loopback if it's unspecified, regardless of
+    /// [Conf::connect_host]
+    pub fn electrum_socket(&self) -> SocketAddr {
+        SocketAddr::new(self.connect_ip, self.electrum_port)
+    }
+
+    /// Return the socket address to connect to the esplora protocol, if enabled, see
+    /// [ElectrsD::electrum_socket]
+    pub fn esplora_socket(&self) -> Option<SocketAddr> {
+        self.esplora_port
+            .map(|port| SocketAddr::new(self.connect_ip, port))
+    }
+
+    /// Return true if the esplora endpoint is enabled, see [Conf::http_enabled]
+    pub fn esplora_enabled(&self) -> bool {
+        self.esplora_url.is_some()
+    }
+
+    /// Return the url of the esplora endpoint, if enabled
+    pub fn esplora_url(&self) -> Option<&str> {
+        self.esplora_url.as_deref()
+    }
+
+    /// Return the url of the monitoring endpoint, if enabled
+    pub fn monitoring_url(&self) -> Option<&str> {
+        self.monitoring_url.as_deref()
+    }
+
+    /// Return the configuration used to create [ElectrsD::client], useful to create other clients
+    /// with the same settings
+    pub fn client_config(&self) -> &electrum_client::Config {
+        &self.client_config
+    }
+
+    /// Return the time electrs took to accept electrum connections since the launch, or esplora
+    /// requests if enabled and later
+    pub fn startup_duration(&self) -> Duration {
+        self.startup_duration
+    }
+
+    /// Change the poll interval and timeout of the wait helpers, initially [Conf::wait]
+    pub fn set_wait_config(&mut self, wait: WaitConfig) {
+        self.wait = wait;
     }
 
     /// Return the current workdir path of the running electrs
@@ -310,18 +874,58 @@ impl ElectrsD {
         self.work_dir.path()
     }
 
-    /// terminate the electrs process
+    /// Return the current workdir path of the running electrs, without allocating
+    pub fn workdir_ref(&self) -> &Path {
+        self.work_dir.path_ref()
+    }
+
+    /// terminate the electrs process, letting it shut down gracefully.
+    ///
+    /// If electrs doesn't exit within [Conf::kill_timeout] it's forcibly killed and
+    /// [Error::KillTimeout] is returned
     pub fn kill(&mut self) -> anyhow::Result<()> {
-        match self.work_dir {
-            DataDir::Persistent(_) => {
-                self.inner_kill()?;
-                // Wait for the process to exit
-                match self.process.wait() {
-                    Ok(_) => Ok(()),
-                    Err(e) => Err(e.into()),
-                }
+        if self.process.try_wait()?.is_some() {
+            return Ok(());
+        }
+        // let electrs close its db, also before a temporary directory is removed
+        let start = Instant::now();
+        self.inner_kill()?;
+        if self.wait_exit(self.kill_timeout)?.is_none() {
+            warn!(
+                "electrs process {} didn't exit after SIGINT, killing it",
+                self.process.id()
+            );
+            self.process.kill()?;
+            // Reap the process so that it doesn't linger as a zombie
+            self.process.wait()?;
+            return Err(Error::KillTimeout(start.elapsed()).into());
+        }
+        Ok(())
+    }
+
+    /// terminate the electrs process and keep its work directory, also when temporary, returning
+    /// its path
+    pub fn kill_and_keep_data(mut self) -> anyhow::Result<PathBuf> {
+        self.kill()?;
+        let path = self.work_dir.path();
+        let work_dir = std::mem::replace(&mut self.work_dir, DataDir::Persistent(path.clone()));
+        if let DataDir::Temporary(tmp_dir) = work_dir {
+            let _ = tmp_dir.keep();
+        }
+        Ok(path)
+    }
+
+    /// Wait up to `timeout` for the process to exit, returns `None` if it's still running
+    fn wait_exit(&mut self, timeout: Duration) -> anyhow::Result<Option<ExitStatus>> {
+        let start = Instant::now();
+        loop {
+            if let Some(status) = self.process.try_wait()? {
+                return Ok(Some(status));
+            }
+            if start.elapsed() > timeout {
+                return Ok(None);
             }
-            DataDir::Temporary(_) => Ok(self.process.kill()?),
+            std::thread::sleep(Duration::from_millis(10));
         }
     }
 
@@ -340,26 +944,895 @@ impl ElectrsD {
     }
 }
 
-impl Drop for ElectrsD {
-    fn drop(&mut self) {
-        let _ = self.kill();
-    }
+/// The bitcoind electrs connects to
+struct Daemon<'b> {
+    client: DaemonClient<'b>,
+    rpc_socket: SocketAddr,
+    p2p_socket: Option<SocketAddr>,
+    cookie_file: PathBuf,
 }
 
-/// Provide the electrs executable path if a version feature has been specified and `ELECTRSD_SKIP_DOWNLOAD` is not set.
-pub fn downloaded_exe_path() -> Option<String> {
-    if versions::HAS_FEATURE && std::env::var_os("ELECTRSD_SKIP_DOWNLOAD").is_none() {
-        Some(format!(
-            "{}/electrs/{}/electrs",
-            env!("OUT_DIR"),
-            versions::electrs_name(),
-        ))
-    } else {
-        None
-    }
+/// RPC client of the bitcoind electrs connects to, borrowed from [BitcoinD] when available
+enum DaemonClient<'b> {
+    Borrowed(&'b bitcoind::bitcoincore_rpc::Client),
+    Owned(bitcoind::bitcoincore_rpc::Client),
 }
 
-/// Returns the daemon `electrs` executable with the following precedence:
+impl std::ops::Deref for DaemonClient<'_> {
+    type Target = bitcoind::bitcoincore_rpc::Client;
+    fn deref(&self) -> &Self::Target {
+        match self {
+            DaemonClient::Borrowed(client) => client,
+            DaemonClient::Owned(client) => client,
+        }
+    }
+}
+
+impl<'b> Daemon<'b> {
+    fn from_bitcoind(bitcoind: &'b BitcoinD, conf: &Conf) -> Result<Self, Error> {
+        let cookie_file = match conf.auth {
+            Auth::Cookie => find_cookie_file(bitcoind)?,
+            Auth::UserPass { .. } => bitcoind.params.cookie_file.clone(),
+        };
+        Ok(Daemon {
+            client: DaemonClient::Borrowed(&bitcoind.client),
+            rpc_socket: bitcoind.params.rpc_socket.into(),
+            p2p_socket: bitcoind.params.p2p_socket.map(Into::into),
+            cookie_file,
+        })
+    }
+
+    fn from_rpc(
+        rpc_socket: SocketAddr,
+        p2p_socket: Option<SocketAddr>,
+        cookie_file: &Path,
+        conf: &Conf,
+    ) -> Result<Self, Error> {
+        let auth = match conf.auth {
+            Auth::Cookie => {
+                if !cookie_file.exists() {
+                    return Err(Error::CookieFileNotFound {
+                        searched: vec![cookie_file.to_path_buf()],
+                    });
+                }
+                bitcoind::bitcoincore_rpc::Auth::CookieFile(cookie_file.to_path_buf())
+            }
+            Auth::UserPass { user, pass } => {
+                bitcoind::bitcoincore_rpc::Auth::UserPass(user.to_string(), pass.to_string())
+            }
+        };
+        let client =
+            bitcoind::bitcoincore_rpc::Client::new(&format!("http://{}", rpc_socket), auth)?;
+        Ok(Daemon {
+            client: DaemonClient::Owned(client),
+            rpc_socket,
+            p2p_socket,
+            cookie_file: cookie_file.to_path_buf(),
+        })
+    }
+}
+
+/// Kills the wrapped process when dropped before being released, so that electrs isn't left
+/// running if the launch fails or panics before [ElectrsD] takes care of it
+struct ChildGuard(Option<Child>);
+
+impl ChildGuard {
+    /// Return the process without killing it
+    fn release(mut self) -> Child {
+        self.0.take().expect("released only once")
+    }
+}
+
+impl std::ops::Deref for ChildGuard {
+    type Target = Child;
+    fn deref(&self) -> &Child {
+        self.0.as_ref().expect("not released")
+    }
+}
+
+impl std::ops::DerefMut for ChildGuard {
+    fn deref_mut(&mut self) -> &mut Child {
+        self.0.as_mut().expect("not released")
+    }
+}
+
+impl Drop for ChildGuard {
+    fn drop(&mut self) {
+        if let Some(process) = &mut self.0 {
+            let _ = process.kill();
+            let _ = process.wait();
+        }
+    }
+}
+
+/// An electrs process launched by [ElectrsD::with_conf] or [ElectrsD::restart], not yet accepting
+/// connections
+struct Launch {
+    process: ChildGuard,
+    command: Command,
+    stderr_capture: Option<StderrCapture>,
+    stderr_reader: Option<JoinHandle<()>>,
+    work_dir: DataDir,
+    electrum_port: u16,
+    electrum_addr: String,
+    electrum_url: String,
+    monitoring_port: Option<u16>,
+    monitoring_addr: Option<String>,
+    monitoring_url: Option<String>,
+    esplora_port: Option<u16>,
+    esplora_url: Option<String>,
+    connect_ip: IpAddr,
+    client_config: electrum_client::Config,
+    cookie: Option<(PathBuf, String)>,
+    start: Instant,
+    spawned: Instant,
+}
+
+/// The state of a [Launch] returned by [Launch::poll]
+// short lived, not worth boxing the variants
+#[allow(clippy::large_enum_variant)]
+enum LaunchState<'a> {
+    /// electrs is accepting connections
+    Connected(RawClient<ElectrumPlaintextStream>),
+    /// electrs exited, it should be launched again with the given [Conf]
+    Exited(Conf<'a>),
+    /// electrs is still starting
+    Starting,
+}
+
+/// Spawn electrs until it accepts connections, or [Conf::attempts] are exhausted, then run the
+/// post start steps. Shared by the blocking and the async constructors, which differ only by how
+/// they `sleep` between the connection attempts.
+async fn launch_with<S, F>(
+    exe: &OsStr,
+    daemon: &Daemon<'_>,
+    conf: &Conf<'_>,
+    sleep: S,
+) -> anyhow::Result<ElectrsD>
+where
+    S: Fn(Duration) -> F,
+    F: Future<Output = ()>,
+{
+    prepare_launch(daemon, conf)?;
+    let mut conf = conf.clone();
+    loop {
+        let mut launch = Launch::spawn(exe, daemon, &conf)?;
+        match launch.wait(&conf, &sleep).await? {
+            LaunchState::Connected(client) => {
+                let electrsd = launch.finish(client, &conf);
+                post_start(&electrsd, daemon, &conf)?;
+                return Ok(electrsd);
+            }
+            LaunchState::Exited(next_conf) => conf = next_conf,
+            LaunchState::Starting => unreachable!("Launch::wait returns only a final state"),
+        }
+    }
+}
+
+/// The blocking `sleep` of [launch_with] and [Launch::wait], out of async contexts
+fn thread_sleep(duration: Duration) -> Ready<()> {
+    std::thread::sleep(duration);
+    std::future::ready(())
+}
+
+/// Run `future` to completion on the current thread, parked while the future is pending
+fn block_on<F: Future>(future: F) -> F::Output {
+    struct ThreadWaker(std::thread::Thread);
+    impl Wake for ThreadWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    let waker = Arc::new(ThreadWaker(std::thread::current())).into();
+    let mut cx = std::task::Context::from_waker(&waker);
+    let mut future = Box::pin(future);
+    loop {
+        match future.as_mut().poll(&mut cx) {
+            Poll::Ready(output) => return output,
+            Poll::Pending => std::thread::park(),
+        }
+    }
+}
+
+impl Launch {
+    /// Prepare the electrs arguments and spawn the process
+    fn spawn(exe: &OsStr, daemon: &Daemon, conf: &Conf) -> anyhow::Result<Launch> {
+        let start = Instant::now();
+        let mut args = conf.args.clone();
+
+        let work_dir = match (&conf.tmpdir, &conf.staticdir) {
+            (Some(_), Some(_)) => return Err(Error::BothDirsSpecified.into()),
+            (Some(tmpdir), None) => DataDir::Temporary(TempDir::new_in(tmpdir)?),
+            (None, Some(workdir)) => {
+                if conf.reindex && workdir.exists() {
+                    debug!("removing {} to reindex", workdir.display());
+                    std::fs::remove_dir_all(workdir)?;
+                }
+                std::fs::create_dir_all(workdir)?;
+                DataDir::Persistent(workdir.to_owned())
+            }
+            (None, None) => match env::var("TEMPDIR_ROOT").map(PathBuf::from) {
+                Ok(path) => DataDir::Temporary(TempDir::new_in(path)?),
+                Err(_) => DataDir::Temporary(TempDir::new()?),
+            },
+        };
+
+        let db_dir = format!("{}", work_dir.path().display());
+        args.push("--db-dir");
+        args.push(&db_dir);
+
+        args.push("--network");
+        args.push(conf.network);
+
+        args.extend(verbosity_args(conf.verbosity));
+
+        let db_parallelism;
+        if let Some(threads) = conf.db_parallelism {
+            if cfg!(feature = "electrs_0_8_10")
+                || cfg!(feature = "electrs_0_9_1")
+                || cfg!(feature = "esplora_a33e97e1")
+                || cfg!(feature = "legacy")
+            {
+                warn!("db_parallelism is not supported by this electrs version, ignoring it");
+            } else {
+                db_parallelism = threads.to_string();
+                args.push("--db-parallelism");
+                args.push(&db_parallelism);
+            }
+        }
+
+        let precache_scripts;
+        if let Some(path) = &conf.precache_scripts {
+            if cfg!(feature = "esplora_a33e97e1") || cfg!(feature = "legacy") {
+                precache_scripts = format!("{}", path.display());
+                args.push("--precache-scripts");
+                args.push(&precache_scripts);
+            } else {
+                warn!("precache_scripts is supported only by esplora, ignoring it");
+            }
+        }
+
+        let config_file;
+        if let Some(path) = &conf.config_file {
+            if cfg!(feature = "esplora_a33e97e1") || cfg!(feature = "legacy") {
+                warn!("config_file is not supported by esplora, ignoring it");
+            } else {
+                // command line arguments take precedence over the config file in electrs
+                let content = std::fs::read_to_string(path)
+                    .with_context(|| format!("Error while reading {}", path.display()))?;
+                for key in overridden_config_keys(&content) {
+                    warn!("{} in {} is overridden by electrsd", key, path.display());
+                }
+                config_file = format!("{}", path.display());
+                args.push("--conf");
+                args.push(&config_file);
+            }
+        }
+
+        let index_batch_size;
+        if let Some(size) = conf.index_batch_size {
+            if cfg!(feature = "esplora_a33e97e1") || cfg!(feature = "legacy") {
+                warn!("index_batch_size is not supported by esplora, ignoring it");
+            } else {
+                index_batch_size = size.to_string();
+                args.push("--index-batch-size");
+                args.push(&index_batch_size);
+            }
+        }
+
+        let index_lookup_limit;
+        if let Some(limit) = conf.index_lookup_limit {
+            if cfg!(feature = "electrs_0_8_10")
+                || cfg!(feature = "esplora_a33e97e1")
+                || cfg!(feature = "legacy")
+            {
+                warn!("index_lookup_limit is not supported by this electrs version, ignoring it");
+            } else {
+                index_lookup_limit = limit.to_string();
+                args.push("--index-lookup-limit");
+                args.push(&index_lookup_limit);
+            }
+        }
+
+        let jsonrpc_timeout;
+        if let Some(timeout) = conf.jsonrpc_timeout {
+            if cfg!(feature = "electrs_0_8_10")
+                || cfg!(feature = "electrs_0_9_1")
+                || cfg!(feature = "esplora_a33e97e1")
+                || cfg!(feature = "legacy")
+            {
+                warn!("jsonrpc_timeout is not supported by this electrs version, ignoring it");
+            } else {
+                jsonrpc_timeout = timeout_secs(timeout).to_string();
+                args.push("--jsonrpc-timeout-secs");
+                args.push(&jsonrpc_timeout);
+            }
+        }
+
+        if let Some(banner) = &conf.server_banner {
+            if cfg!(feature = "esplora_a33e97e1") || cfg!(feature = "legacy") {
+                args.push("--electrum-banner");
+            } else {
+                args.push("--server-banner");
+            }
+            args.push(banner);
+        }
+
+        #[cfg(not(feature = "legacy"))]
+        let cookie_file;
+        #[cfg(feature = "legacy")]
+        let mut cookie_value;
+        let user_pass;
+        #[cfg_attr(not(feature = "legacy"), allow(unused_mut))]
+        let mut cookie: Option<(PathBuf, String)> = None;
+        match &conf.auth {
+            Auth::Cookie => {
+                let cookie_path = &daemon.cookie_file;
+
+                #[cfg(not(feature = "legacy"))]
+                {
+                    args.push("--cookie-file");
+                    cookie_file = format!("{}", cookie_path.display());
+                    args.push(&cookie_file);
+                }
+
+                #[cfg(feature = "legacy")]
+                {
+                    use std::io::Read;
+                    args.push("--cookie");
+                    let mut cookie_reader = std::fs::File::open(cookie_path)?;
+                    cookie_value = String::new();
+                    cookie_reader.read_to_string(&mut cookie_value)?;
+                    args.push(&cookie_value);
+                    cookie = Some((cookie_path.clone(), cookie_value.clone()));
+                }
+            }
+            Auth::UserPass { user, pass } => {
+                if cfg!(feature = "electrs_0_8_10")
+                    || cfg!(feature = "esplora_a33e97e1")
+                    || cfg!(feature = "legacy")
+                {
+                    args.push("--cookie");
+                } else {
+                    args.push("--auth");
+                }
+                user_pass = format!("{}:{}", user, pass);
+                args.push(&user_pass);
+            }
+        }
+
+        args.push("--daemon-rpc-addr");
+        let rpc_socket = daemon.rpc_socket.to_string();
+        args.push(&rpc_socket);
+
+        let daemon_dir;
+        if let Some(dir) = &conf.daemon_dir {
+            daemon_dir = format!("{}", dir.display());
+            args.push("--daemon-dir");
+            args.push(&daemon_dir);
+        }
+
+        let p2p_socket;
+        if cfg!(feature = "electrs_0_8_10")
+            || cfg!(feature = "esplora_a33e97e1")
+            || cfg!(feature = "legacy")
+        {
+            // with the bitcoind data directory blocks are read from the block files instead
+            if conf.daemon_dir.is_none() {
+                args.push("--jsonrpc-import");
+            }
+        } else {
+            args.push("--daemon-p2p-addr");
+            p2p_socket = daemon.p2p_socket.ok_or(Error::P2pPortRequired)?.to_string();
+            args.push(&p2p_socket);
+        }
+
+        let electrum_port = match conf.electrum_port {
+            Some(port) => fixed_port(conf.listen_addr, port)?,
+            None => get_available_port()?,
+        };
+        let connect_ip = match conf.listen_addr {
+            IpAddr::V4(ip) if ip.is_unspecified() => Ipv4Addr::LOCALHOST.into(),
+            IpAddr::V6(ip) if ip.is_unspecified() => Ipv6Addr::LOCALHOST.into(),
+            ip => ip,
+        };
+        let connect_url = |port: u16| match conf.connect_host {
+            Some(connect_host) => format!("{}:{}", connect_host, port),
+            None => SocketAddr::new(connect_ip, port).to_string(),
+        };
+
+        let electrum_addr = SocketAddr::new(conf.listen_addr, electrum_port).to_string();
+        args.push("--electrum-rpc-addr");
+        args.push(&electrum_addr);
+        let electrum_url = connect_url(electrum_port);
+
+        let monitoring_port = if conf.monitoring {
+            Some(get_available_port()?)
+        } else {
+            None
+        };
+        let monitoring_addr =
+            monitoring_port.map(|port| SocketAddr::new(conf.listen_addr, port).to_string());
+        // when disabled the OS picks the port, on its default one electrs would collide with the
+        // other instances
+        let monitoring_arg =
+            SocketAddr::new(conf.listen_addr, monitoring_port.unwrap_or(0)).to_string();
+        args.push("--monitoring-addr");
+        args.push(&monitoring_arg);
+        let monitoring_url = monitoring_port.map(connect_url);
+
+        let esplora_port = match (conf.http_enabled, conf.esplora_port) {
+            (true, Some(port)) => Some(fixed_port(conf.listen_addr, port)?),
+            (true, None) => Some(get_available_port()?),
+            (false, _) => None,
+        };
+        let esplora_addr;
+        let esplora_url = match esplora_port {
+            Some(esplora_port) => {
+                esplora_addr = SocketAddr::new(conf.listen_addr, esplora_port).to_string();
+                args.push("--http-addr");
+                args.push(&esplora_addr);
+                Some(connect_url(esplora_port))
+            }
+            None => None,
+        };
+
+        let stderr_capture = if conf.capture_stderr {
+            Some(StderrCapture::new(
+                conf.stderr_buffer_lines,
+                conf.view_stderr,
+            ))
+        } else {
+            None
+        };
+        let view_stderr = if conf.capture_stderr {
+            Stdio::piped()
+        } else if conf.view_stderr {
+            Stdio::inherit()
+        } else {
+            Stdio::null()
+        };
+
+        let mut command = Command::new(exe);
+        command
+            .args(args)
+            .envs(conf.env.iter().copied())
+            .stderr(view_stderr);
+        #[cfg(not(target_os = "windows"))]
+        if conf.process_group {
+            use std::os::unix::process::CommandExt;
+            command.process_group(0);
+        }
+        if let Some(SpawnHook(hook)) = &conf.spawn_hook {
+            hook(&mut command);
+        }
+        debug!("command: {}", command_line(&command));
+        let mut process =
+            ChildGuard(Some(command.spawn().with_context(|| {
+                format!("Error while executing {}", command_line(&command))
+            })?));
+        let stderr_reader = stderr_capture
+            .as_ref()
+            .and_then(|capture| capture.start(&mut process));
+
+        Ok(Launch {
+            process,
+            command,
+            stderr_capture,
+            stderr_reader,
+            work_dir,
+            electrum_port,
+            electrum_addr,
+            electrum_url,
+            monitoring_port,
+            monitoring_addr,
+            monitoring_url,
+            esplora_port,
+            esplora_url,
+            connect_ip,
+            client_config: electrum_client::Config::default(),
+            cookie,
+            start,
+            spawned: Instant::now(),
+        })
+    }
+
+    /// Spawn again the electrs command of `electrsd`, thus with the same ports, taking over its
+    /// work directory until [Launch::finish] or [Launch::restore]
+    fn respawn(electrsd: &mut ElectrsD) -> anyhow::Result<Launch> {
+        let start = Instant::now();
+        let mut process = ChildGuard(Some(electrsd.command.spawn().with_context(|| {
+            format!("Error while restarting {}", command_line(&electrsd.command))
+        })?));
+        let stderr_capture = electrsd.stderr_capture.take();
+        let stderr_reader = stderr_capture
+            .as_ref()
+            .and_then(|capture| capture.start(&mut process));
+        let connect_ip = electrsd.connect_ip;
+        let address = |port: u16| SocketAddr::new(connect_ip, port).to_string();
+        let workdir = electrsd.workdir();
+        Ok(Launch {
+            process,
+            command: std::mem::replace(&mut electrsd.command, Command::new("")),
+            stderr_capture,
+            stderr_reader,
+            work_dir: std::mem::replace(&mut electrsd.work_dir, DataDir::Persistent(workdir)),
+            electrum_port: electrsd.electrum_port,
+            electrum_addr: address(electrsd.electrum_port),
+            electrum_url: electrsd.electrum_url.clone(),
+            monitoring_port: electrsd.monitoring_port,
+            monitoring_addr: electrsd.monitoring_port.map(address),
+            monitoring_url: electrsd.monitoring_url.clone(),
+            esplora_port: electrsd.esplora_port,
+            esplora_url: electrsd.esplora_url.clone(),
+            connect_ip,
+            client_config: electrsd.client_config.clone(),
+            cookie: electrsd.cookie.take(),
+            start,
+            spawned: start,
+        })
+    }
+
+    /// Give back to `electrsd` what [Launch::respawn] took, the new process is killed
+    fn restore(self, electrsd: &mut ElectrsD) {
+        electrsd.command = self.command;
+        electrsd.stderr_capture = self.stderr_capture;
+        electrsd.work_dir = self.work_dir;
+        electrsd.cookie = self.cookie;
+    }
+
+    /// [Launch::poll] until electrs accepts connections or exits, sleeping with `sleep` in between.
+    /// Never returns [LaunchState::Starting].
+    async fn wait<'a, S, F>(
+        &mut self,
+        conf: &Conf<'a>,
+        sleep: &S,
+    ) -> anyhow::Result<LaunchState<'a>>
+    where
+        S: Fn(Duration) -> F,
+        F: Future<Output = ()>,
+    {
+        loop {
+            match self.poll(conf)? {
+                LaunchState::Starting => sleep(Duration::from_millis(500)).await,
+                state => return Ok(state),
+            }
+        }
+    }
+
+    /// Check once whether electrs is accepting connections
+    fn poll<'a>(&mut self, conf: &Conf<'a>) -> anyhow::Result<LaunchState<'a>> {
+        if let Some(status) = self.process.try_wait()? {
+            if self.monitoring_addr.as_deref().is_some_and(is_port_in_use)
+                && !is_port_in_use(&self.electrum_addr)
+            {
+                // a conflict on the monitoring port only shouldn't consume an attempt
+                warn!("early exit with: {:?}. Monitoring address {:?} is in use by another process, launching again with a different one", status, self.monitoring_addr);
+                return Ok(LaunchState::Exited(conf.clone()));
+            }
+            if conf.attempts > 0 {
+                warn!("early exit with: {:?}. Trying to launch again ({} attempts remaining), maybe some other process used our available port", status, conf.attempts);
+                let mut conf = conf.clone();
+                conf.attempts -= 1;
+                return Ok(LaunchState::Exited(conf));
+            }
+            error!("early exit with: {:?}", status);
+            if let Some(stderr_reader) = self.stderr_reader.take() {
+                // the process exited, the reader ends once it has read the remaining output
+                let _ = stderr_reader.join();
+            }
+            return Err(Error::EarlyExit {
+                status,
+                command: command_line(&self.command),
+                electrum_port: self.electrum_port,
+                monitoring_port: self.monitoring_port,
+                esplora_port: self.esplora_port,
+                stderr: stderr_lines(&self.stderr_capture),
+            }
+            .into());
+        }
+        if let Some(startup_timeout) = conf.startup_timeout {
+            let waited = self.spawned.elapsed();
+            if waited > startup_timeout {
+                error!("electrs didn't accept connections after {:?}", waited);
+                let _ = self.process.kill();
+                let _ = self.process.wait();
+                return Err(Error::StartupTimeout(waited).into());
+            }
+        }
+        match RawClient::new(&self.electrum_url, self.client_config.timeout()) {
+            // the esplora endpoint may accept requests later than the electrum one
+            Ok(_)
+                if self
+                    .esplora_url
+                    .as_deref()
+                    .is_some_and(|url| !esplora_ready(url)) =>
+            {
+                Ok(LaunchState::Starting)
+            }
+            Ok(client) => Ok(LaunchState::Connected(client)),
+            Err(e) if is_not_ready(&e) => Ok(LaunchState::Starting),
+            Err(e) => Err(Error::ElectrumClient(e).into()),
+        }
+    }
+
+    /// Complete the launch once electrs is accepting connections
+    fn finish(self, client: RawClient<ElectrumPlaintextStream>, conf: &Conf) -> ElectrsD {
+        ElectrsD {
+            process: self.process.release(),
+            command: self.command,
+            stderr_capture: self.stderr_capture,
+            client,
+            work_dir: self.work_dir,
+            electrum_url: self.electrum_url,
+            esplora_url: self.esplora_url,
+            monitoring_url: self.monitoring_url,
+            electrum_port: self.electrum_port,
+            esplora_port: self.esplora_port,
+            monitoring_port: self.monitoring_port,
+            connect_ip: self.connect_ip,
+            client_config: self.client_config,
+            startup_duration: self.start.elapsed(),
+            startup_timeout: conf.startup_timeout,
+            wait: conf.wait,
+            kill_timeout: conf.kill_timeout,
+            cookie: self.cookie,
+        }
+    }
+}
+
+/// Checks and bitcoind calls done once after electrs started, see [Conf::verify_genesis] and
+/// [Conf::post_start]
+fn post_start(electrsd: &ElectrsD, daemon: &Daemon, conf: &Conf) -> anyhow::Result<()> {
+    if conf.verify_genesis {
+        match Network::from_str(conf.network) {
+            Ok(network) => {
+                let expected = genesis_block(network).block_hash();
+                let found = electrsd.client.block_header(0)?.block_hash();
+                if expected != found {
+                    return Err(Error::GenesisMismatch { expected, found }.into());
+                }
+            }
+            Err(_) => warn!("unknown network {}, cannot verify genesis", conf.network),
+        }
+    }
+
+    for (method, params) in conf.post_start.iter() {
+        debug!("post start call: {} {:?}", method, params);
+        daemon.client.call::<Value>(method, params)?;
+    }
+    Ok(())
+}
+
+impl Drop for ElectrsD {
+    fn drop(&mut self) {
+        let _ = self.kill();
+    }
+}
+
+/// Checks done and bitcoind setup needed once before the launch attempts of electrs
+fn prepare_launch(daemon: &Daemon, conf: &Conf) -> anyhow::Result<()> {
+    check_reserved_flags(&conf.args)?;
+    if conf.allow_unknown_network && conf.skip_ibd_check {
+        return Ok(());
+    }
+
+    let response = daemon.client.call::<Value>("getblockchaininfo", &[])?;
+    let chain = response.get("chain").and_then(|v| v.as_str()).unwrap_or("");
+    if !conf.allow_unknown_network && !network_matches_chain(conf.network, chain) {
+        return Err(Error::NetworkMismatch {
+            expected: conf.network.to_string(),
+            actual: chain.to_string(),
+        }
+        .into());
+    }
+    // blocks can be generated on demand only on regtest
+    if !conf.skip_ibd_check
+        && chain == "regtest"
+        && response
+            .get("initialblockdownload")
+            .and_then(|v| v.as_bool())
+            .unwrap_or(false)
+    {
+        // electrum will remain idle until bitcoind is in IBD
+        // bitcoind will remain in IBD if doesn't see a block from a long time, thus adding a block
+        let node_address = daemon.client.call::<Value>("getnewaddress", &[])?;
+        daemon
+            .client
+            .call::<Value>("generatetoaddress", &[1.into(), node_address])?;
+        debug!("bitcoind was in initial block download, generated a block to get it out");
+    }
+    Ok(())
+}
+
+/// Returns true if the electrs network is the bitcoind chain, as returned by `getblockchaininfo`
+fn network_matches_chain(network: &str, chain: &str) -> bool {
+    match chain {
+        "main" => network == "bitcoin" || network == "mainnet",
+        "test" => network == "testnet",
+        _ => network == chain,
+    }
+}
+
+/// Flags automatically set by [ElectrsD::with_conf], thus not allowed in [Conf::args]
+const RESERVED_FLAGS: &[&str] = &[
+    "--db-dir",
+    "--cookie",
+    "--cookie-file",
+    "--auth",
+    "--daemon-rpc-addr",
+    "--daemon-p2p-addr",
+    "--jsonrpc-import",
+    "--electrum-rpc-addr",
+    "--monitoring-addr",
+    "--http-addr",
+    "--network",
+];
+
+/// Returns an error if any of the given args is a flag in [RESERVED_FLAGS]
+fn check_reserved_flags(args: &[&str]) -> Result<(), Error> {
+    for arg in args {
+        let flag = arg.split('=').next().unwrap_or(arg);
+        if RESERVED_FLAGS.contains(&flag) {
+            return Err(Error::ReservedFlag(flag.to_string()));
+        }
+    }
+    Ok(())
+}
+
+/// Returns the keys of the given electrs config file content set automatically by
+/// [ElectrsD::with_conf] through the [RESERVED_FLAGS]
+fn overridden_config_keys(content: &str) -> Vec<&str> {
+    content
+        .lines()
+        .filter_map(|line| line.split_once('='))
+        .map(|(key, _)| key.trim())
+        .filter(|key| {
+            let flag = format!("--{}", key.replace('_', "-"));
+            RESERVED_FLAGS.contains(&flag.as_str())
+        })
+        .collect()
+}
+
+/// Returns the bitcoind cookie file path, when it isn't found where bitcoind reports it, the
+/// network subdirectories of the bitcoind work directory are searched
+fn find_cookie_file(bitcoind: &BitcoinD) -> Result<PathBuf, Error> {
+    let cookie_file = &bitcoind.params.cookie_file;
+    if cookie_file.exists() {
+        return Ok(cookie_file.clone());
+    }
+    let workdir = bitcoind.workdir();
+    let mut searched = vec![cookie_file.clone()];
+    for subdir in ["", "regtest", "testnet3", "testnet4", "signet"] {
+        let candidate = workdir.join(subdir).join(".cookie");
+        if candidate.exists() {
+            return Ok(candidate);
+        }
+        searched.push(candidate);
+    }
+    Err(Error::CookieFileNotFound { searched })
+}
+
+/// Returns the lines captured by the given capture, if any
+fn stderr_lines(stderr_capture: &Option<StderrCapture>) -> Vec<String> {
+    stderr_capture
+        .as_ref()
+        .map(StderrCapture::lines)
+        .unwrap_or_default()
+}
+
+/// Returns true if the electrum connection error means electrs isn't listening yet, other errors
+/// like an unresolvable [Conf::connect_host] won't go away retrying
+fn is_not_ready(error: &electrum_client::Error) -> bool {
+    use std::io::ErrorKind;
+    match error {
+        electrum_client::Error::IOError(e) => matches!(
+            e.kind(),
+            ErrorKind::ConnectionRefused
+                | ErrorKind::ConnectionReset
+                | ErrorKind::ConnectionAborted
+                | ErrorKind::NotConnected
+                | ErrorKind::TimedOut
+                | ErrorKind::WouldBlock
+        ),
+        electrum_client::Error::AllAttemptsErrored(errors) => errors.iter().all(is_not_ready),
+        _ => false,
+    }
+}
+
+/// Returns true if something else is listening on the given address
+fn is_port_in_use(addr: &str) -> bool {
+    TcpListener::bind(addr).is_err()
+}
+
+/// The command line of `command` as shown in errors, with the credentials given to `--cookie` and
+/// `--auth` redacted
+fn command_line(command: &Command) -> String {
+    let mut redact = false;
+    std::iter::once(command.get_program())
+        .chain(command.get_args())
+        .map(|arg| {
+            let shown = if redact {
+                "<redacted>".to_string()
+            } else {
+                format!("{:?}", arg)
+            };
+            redact = arg == "--cookie" || arg == "--auth";
+            shown
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Returns the given port if it's free on the given ip, [Error::PortInUse] otherwise
+fn fixed_port(ip: IpAddr, port: u16) -> Result<u16, Error> {
+    if is_port_in_use(&SocketAddr::new(ip, port).to_string()) {
+        return Err(Error::PortInUse(port));
+    }
+    Ok(port)
+}
+
+/// Return the electrs version of the enabled version feature, like `v0.9.11`, `None` if no version
+/// feature is enabled
+pub fn enabled_version() -> Option<&'static str> {
+    if versions::HAS_FEATURE {
+        Some(versions::VERSION)
+    } else {
+        None
+    }
+}
+
+/// Provide the electrs executable path if a version feature has been specified and `ELECTRSD_SKIP_DOWNLOAD` is not set.
+///
+/// The path is the one given in the `ELECTRSD_DOWNLOAD_DEST` env var at build time, if any,
+/// otherwise the executable is looked up in the `ELECTRSD_DOWNLOAD_DIR` cache directory given at
+/// build time, falling back to the `OUT_DIR`.
+///
+/// Returns `None` also when there is no executable file at the resulting path, for example
+/// because the download was skipped or failed.
+pub fn downloaded_exe_path() -> Option<String> {
+    if !versions::HAS_FEATURE || std::env::var_os("ELECTRSD_SKIP_DOWNLOAD").is_some() {
+        return None;
+    }
+    let cached = option_env!("ELECTRSD_DOWNLOAD_DIR")
+        .map(|dir| {
+            PathBuf::from(dir)
+                .join(versions::electrs_name())
+                .join(versions::EXE_NAME)
+        })
+        .filter(|path| path.exists());
+    let path = match (option_env!("ELECTRSD_DOWNLOAD_DEST"), cached) {
+        (Some(path), _) => PathBuf::from(path),
+        (None, Some(path)) => path,
+        (None, None) => PathBuf::from(env!("OUT_DIR"))
+            .join("electrs")
+            .join(versions::electrs_name())
+            .join(versions::EXE_NAME),
+    };
+    if is_executable(&path) {
+        Some(path.display().to_string())
+    } else {
+        None
+    }
+}
+
+/// Whether `path` is a file the current user could execute, only its existence is checked on
+/// windows
+fn is_executable(path: &Path) -> bool {
+    match std::fs::metadata(path) {
+        #[cfg(unix)]
+        Ok(metadata) => {
+            use std::os::unix::fs::PermissionsExt;
+            metadata.is_file() && metadata.permissions().mode() & 0o111 != 0
+        }
+        #[cfg(not(unix))]
+        Ok(metadata) => metadata.is_file(),
+        Err(_) => false,
+    }
+}
+
+/// Returns the daemon `electrs` executable with the following precedence:
 ///
 /// 1) If it's specified in the `ELECTRS_EXEC` or in `ELECTRS_EXE` env var (errors if both env vars are present)
 /// 2) If there is no env var but an auto-download feature such as `electrs_0_9_11` is enabled, returns the path of the downloaded executabled
@@ -374,33 +1847,263 @@ pub fn exe_path() -> anyhow::Result<String> {
     if let Ok(path) = std::env::var("ELECTRS_EXE") {
         return Ok(path);
     }
-    if let Some(path) = downloaded_exe_path() {
-        return Ok(path);
+    if let Some(path) = downloaded_exe_path() {
+        return Ok(path);
+    }
+    which::which("electrs")
+        .map_err(|_| Error::NoElectrsExecutableFound.into())
+        .map(|p| p.display().to_string())
+}
+
+/// Checks the electrs executable is compatible with the arguments used with the enabled version
+/// feature, for example an electrs `v0.9.x` found in `PATH` can't be used with the `electrs_0_8_10`
+/// feature because it doesn't support `--jsonrpc-import`.
+///
+/// Returns [Error::VersionFeatureMismatch] on conflict, the check is skipped for esplora
+/// executables which follow a different versioning.
+pub fn validate_exe(exe: &OsStr) -> anyhow::Result<()> {
+    if cfg!(feature = "esplora_a33e97e1") || cfg!(feature = "legacy") {
+        return Ok(());
+    }
+    let output = Command::new(exe)
+        .arg("--version")
+        .output()
+        .with_context(|| format!("Error while executing {:?}", exe))?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let detected = match parse_version(&stdout) {
+        Some(version) => version,
+        None => {
+            warn!("cannot parse electrs version from {:?}", stdout);
+            return Ok(());
+        }
+    };
+    let uses_jsonrpc_import = cfg!(feature = "electrs_0_8_10");
+    let supports_p2p = detected >= (0, 9, 0);
+    if uses_jsonrpc_import == supports_p2p {
+        return Err(Error::VersionFeatureMismatch {
+            feature: versions::VERSION,
+            detected: stdout.trim().to_string(),
+        }
+        .into());
+    }
+    Ok(())
+}
+
+/// Parse the first `major.minor.patch` version found in the given text, such as `v0.9.11`
+fn parse_version(text: &str) -> Option<(u32, u32, u32)> {
+    text.split_whitespace().find_map(|token| {
+        let mut numbers = token.trim_start_matches('v').split('.').map(|n| {
+            let digits: String = n.chars().take_while(|c| c.is_ascii_digit()).collect();
+            digits.parse::<u32>().ok()
+        });
+        Some((numbers.next()??, numbers.next()??, numbers.next()??))
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use crate::bitcoind::P2P;
+    use crate::exe_path;
+    use crate::{
+        check_reserved_flags, command_line, is_executable, is_not_ready, network_matches_chain,
+        overridden_config_keys, parse_version, timeout_secs, verbosity_args, ElectrsD, Error,
+    };
+    use bitcoind::bitcoincore_rpc::RpcApi;
+    use electrum_client::ElectrumApi;
+    use log::{debug, log_enabled, Level};
+    use std::env;
+    use std::net::{Ipv4Addr, SocketAddr};
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    #[ignore] // launch singularly since env are globals
+    fn test_both_env_vars() {
+        env::set_var("ELECTRS_EXEC", "placeholder");
+        env::set_var("ELECTRS_EXE", "placeholder");
+        assert!(exe_path().is_err());
+        // unsetting because this errors everything in mod test!
+        env::remove_var("ELECTRS_EXEC");
+        env::remove_var("ELECTRS_EXE");
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_conf_deserialize() {
+        use bitcoind::bitcoincore_rpc::jsonrpc::serde_json;
+        let json = r#"{"args": ["-v"], "network": "signet", "http_enabled": true}"#;
+        let conf: crate::Conf = serde_json::from_str(json).unwrap();
+        let expected = crate::Conf {
+            args: vec!["-v"],
+            network: "signet",
+            http_enabled: true,
+            ..Default::default()
+        };
+        assert_eq!(conf, expected);
+
+        let json = r#"{"auth": {"UserPass": {"user": "alice", "pass": "secret"}}}"#;
+        let conf: crate::Conf = serde_json::from_str(json).unwrap();
+        let expected = crate::Conf {
+            auth: crate::Auth::UserPass {
+                user: "alice",
+                pass: "secret",
+            },
+            ..Default::default()
+        };
+        assert_eq!(conf, expected);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_conf_deserialize_file() {
+        use bitcoind::bitcoincore_rpc::jsonrpc::serde_json;
+        let dir = crate::TempDir::new().unwrap();
+        let path = dir.path().join("conf.json");
+        std::fs::write(&path, r#"{"network": "signet", "args": ["-vv"]}"#).unwrap();
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        let conf: crate::Conf = serde_json::from_str(&content).unwrap();
+        assert_eq!(conf.network, "signet");
+        assert_eq!(conf.args, vec!["-vv"]);
+
+        assert!(serde_json::from_str::<crate::Conf>(r#"{"network": "sig\u006eet"}"#).is_err());
+    }
+
+    #[test]
+    fn test_reserved_flags() {
+        assert!(check_reserved_flags(&["-vvv", "--index-batch-size", "10"]).is_ok());
+        assert!(matches!(
+            check_reserved_flags(&["-vvv", "--db-dir", "/tmp"]),
+            Err(Error::ReservedFlag(flag)) if flag == "--db-dir"
+        ));
+        assert!(matches!(
+            check_reserved_flags(&["--network=testnet"]),
+            Err(Error::ReservedFlag(flag)) if flag == "--network"
+        ));
+    }
+
+    #[test]
+    #[ignore] // launch singularly since env are globals
+    fn test_conf_from_env() {
+        std::env::set_var("ELECTRSD_VIEW_STDERR", "1");
+        std::env::set_var("ELECTRSD_HTTP_ENABLED", "true");
+        std::env::set_var("ELECTRSD_NETWORK", "signet");
+        std::env::set_var("ELECTRSD_STATICDIR", "/tmp/electrsd");
+        std::env::set_var("ELECTRSD_VERBOSITY", "4");
+        let conf = crate::Conf::from_env();
+        for var in [
+            "ELECTRSD_VIEW_STDERR",
+            "ELECTRSD_HTTP_ENABLED",
+            "ELECTRSD_NETWORK",
+            "ELECTRSD_STATICDIR",
+            "ELECTRSD_VERBOSITY",
+        ] {
+            std::env::remove_var(var);
+        }
+
+        let expected = crate::Conf {
+            view_stderr: true,
+            http_enabled: true,
+            network: "signet",
+            staticdir: Some("/tmp/electrsd".into()),
+            verbosity: 4,
+            ..Default::default()
+        };
+        assert_eq!(conf, expected);
+
+        std::env::set_var("ELECTRSD_NETWORK", "liquidv1");
+        let conf = crate::Conf::from_env();
+        std::env::remove_var("ELECTRSD_NETWORK");
+        assert_eq!(conf.network, "regtest");
+    }
+
+    #[test]
+    fn test_command_line() {
+        let mut command = std::process::Command::new("electrs");
+        command.args(["--auth", "user:pass", "--network", "regtest", "--cookie"]);
+        assert_eq!(
+            command_line(&command),
+            r#""electrs" "--auth" <redacted> "--network" "regtest" "--cookie""#
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_is_executable() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = bitcoind::tempfile::tempdir().unwrap();
+        let exe = dir.path().join("electrs");
+        assert!(!is_executable(&exe));
+        std::fs::write(&exe, "").unwrap();
+        std::fs::set_permissions(&exe, std::fs::Permissions::from_mode(0o644)).unwrap();
+        assert!(!is_executable(&exe));
+        std::fs::set_permissions(&exe, std::fs::Permissions::from_mode(0o755)).unwrap();
+        assert!(is_executable(&exe));
+        assert!(!is_executable(dir.path()));
+    }
+
+    #[test]
+    fn test_overridden_config_keys() {
+        let content = "db_dir = \"/tmp/db\"\nindex_batch_size = 10\n# comment\n network=\"signet\"";
+        assert_eq!(overridden_config_keys(content), vec!["db_dir", "network"]);
+    }
+
+    #[test]
+    fn test_is_not_ready() {
+        use std::io::{Error as IoError, ErrorKind};
+        let refused = electrum_client::Error::IOError(IoError::from(ErrorKind::ConnectionRefused));
+        assert!(is_not_ready(&refused));
+        let lookup =
+            electrum_client::Error::IOError(IoError::other("failed to lookup address information"));
+        assert!(!is_not_ready(&lookup));
+    }
+
+    #[test]
+    fn test_network_matches_chain() {
+        assert!(network_matches_chain("regtest", "regtest"));
+        assert!(network_matches_chain("bitcoin", "main"));
+        assert!(network_matches_chain("testnet", "test"));
+        assert!(network_matches_chain("signet", "signet"));
+        assert!(!network_matches_chain("regtest", "signet"));
+        assert!(!network_matches_chain("bitcoin", "test"));
+    }
+
+    #[test]
+    fn test_verbosity_args() {
+        let legacy = cfg!(feature = "electrs_0_9_1")
+            || cfg!(feature = "electrs_0_8_10")
+            || cfg!(feature = "esplora_a33e97e1")
+            || cfg!(feature = "legacy");
+        assert!(verbosity_args(0).is_empty());
+        for (verbosity, flag, level) in [
+            (1, "-v", "WARN"),
+            (2, "-vv", "INFO"),
+            (3, "-vvv", "DEBUG"),
+            (4, "-vvvv", "TRACE"),
+            (5, "-vvvv", "TRACE"),
+        ] {
+            let expected = if legacy {
+                vec![flag]
+            } else {
+                vec!["--log-filters", level]
+            };
+            assert_eq!(verbosity_args(verbosity), expected);
+        }
     }
-    which::which("electrs")
-        .map_err(|_| Error::NoElectrsExecutableFound.into())
-        .map(|p| p.display().to_string())
-}
 
-#[cfg(test)]
-mod test {
-    use crate::bitcoind::P2P;
-    use crate::exe_path;
-    use crate::ElectrsD;
-    use bitcoind::bitcoincore_rpc::RpcApi;
-    use electrum_client::ElectrumApi;
-    use log::{debug, log_enabled, Level};
-    use std::env;
+    #[test]
+    fn test_timeout_secs() {
+        assert_eq!(timeout_secs(Duration::from_secs(30)), 30);
+        assert_eq!(timeout_secs(Duration::from_millis(1500)), 2);
+        assert_eq!(timeout_secs(Duration::from_millis(500)), 1);
+        assert_eq!(timeout_secs(Duration::ZERO), 1);
+    }
 
     #[test]
-    #[ignore] // launch singularly since env are globals
-    fn test_both_env_vars() {
-        env::set_var("ELECTRS_EXEC", "placeholder");
-        env::set_var("ELECTRS_EXE", "placeholder");
-        assert!(exe_path().is_err());
-        // unsetting because this errors everything in mod test!
-        env::remove_var("ELECTRS_EXEC");
-        env::remove_var("ELECTRS_EXE");
+    fn test_parse_version() {
+        assert_eq!(parse_version("v0.9.11"), Some((0, 9, 11)));
+        assert_eq!(parse_version("electrs 0.10.6-rc1\n"), Some((0, 10, 6)));
+        assert_eq!(parse_version("electrs"), None);
     }
 
     #[test]
@@ -415,6 +2118,7 @@ mod test {
             .assume_checked();
         bitcoind.client.generate_to_address(100, &address).unwrap();
 
+        #[cfg(not(target_os = "windows"))]
         electrsd.trigger().unwrap();
 
         let header = loop {
@@ -432,26 +2136,619 @@ mod test {
         assert_eq!(header.height, 101);
     }
 
+    #[test]
+    fn test_sockets() {
+        let (_, _bitcoind, electrsd) = setup_nodes();
+        assert!(electrsd
+            .electrum_url
+            .ends_with(&format!(":{}", electrsd.electrum_port())));
+        assert_eq!(
+            electrsd.electrum_socket().to_string(),
+            electrsd.electrum_url
+        );
+        assert_eq!(electrsd.esplora_port(), None);
+        assert_eq!(electrsd.esplora_socket(), None);
+    }
+
+    #[test]
+    fn test_version() {
+        let (_, _bitcoind, electrsd) = setup_nodes();
+        assert_eq!(electrsd.version().is_some(), crate::versions::HAS_FEATURE);
+        assert_eq!(electrsd.version(), crate::enabled_version());
+    }
+
+    #[test]
+    fn test_post_start() {
+        let (electrs_exe, bitcoind) = setup_bitcoind();
+        let address = bitcoind
+            .client
+            .get_new_address(None, None)
+            .unwrap()
+            .assume_checked();
+        let conf = crate::Conf {
+            post_start: vec![(
+                "generatetoaddress",
+                vec![10.into(), address.to_string().into()],
+            )],
+            ..Default::default()
+        };
+        let electrsd = ElectrsD::with_conf(&electrs_exe, &bitcoind, &conf).unwrap();
+        assert_eq!(bitcoind.client.get_block_count().unwrap(), 11);
+        #[cfg(not(target_os = "windows"))]
+        electrsd.trigger().unwrap();
+        electrsd
+            .wait_until_behind_at_most(&bitcoind, 0, std::time::Duration::from_secs(60))
+            .unwrap();
+    }
+
+    #[test]
+    fn test_verify_genesis() {
+        let (electrs_exe, bitcoind) = setup_bitcoind();
+        let conf = crate::Conf {
+            verify_genesis: true,
+            ..Default::default()
+        };
+        ElectrsD::with_conf(&electrs_exe, &bitcoind, &conf).unwrap();
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn test_with_conf_async() {
+        use std::future::Future;
+        use std::sync::Arc;
+        use std::task::{Context, Poll, Wake};
+
+        struct ThreadWaker(std::thread::Thread);
+        impl Wake for ThreadWaker {
+            fn wake(self: Arc<Self>) {
+                self.0.unpark();
+            }
+        }
+        fn assert_send<T: Send>(_: &T) {}
+
+        let (electrs_exe, bitcoind) = setup_bitcoind();
+        let waker = Arc::new(ThreadWaker(std::thread::current())).into();
+        let mut cx = Context::from_waker(&waker);
+        let conf = crate::Conf::default();
+        let mut future = Box::pin(ElectrsD::with_conf_async(&electrs_exe, &bitcoind, &conf));
+        assert_send(&future);
+        let electrsd = loop {
+            match future.as_mut().poll(&mut cx) {
+                Poll::Ready(result) => break result.unwrap(),
+                Poll::Pending => std::thread::park(),
+            }
+        };
+        electrsd.client.ping().unwrap();
+    }
+
+    #[cfg(not(feature = "electrs_0_8_10"))]
+    #[test]
+    fn test_restart() {
+        let (_, bitcoind, mut electrsd) = setup_nodes();
+        let address = bitcoind
+            .client
+            .get_new_address(None, None)
+            .unwrap()
+            .assume_checked();
+        bitcoind.client.generate_to_address(10, &address).unwrap();
+        #[cfg(not(target_os = "windows"))]
+        electrsd.trigger().unwrap();
+        electrsd.wait_height(11);
+        let workdir = electrsd.workdir();
+
+        electrsd.restart().unwrap();
+        assert_eq!(electrsd.workdir(), workdir);
+        electrsd.wait_height(11);
+        let header = electrsd.client.block_headers_subscribe().unwrap();
+        assert_eq!(header.height, 11);
+    }
+
+    #[test]
+    fn test_restart_after_crash() {
+        let (_, _bitcoind, mut electrsd) = setup_nodes();
+        electrsd.process.kill().unwrap();
+        electrsd.process.wait().unwrap();
+        assert!(!electrsd.is_running());
+
+        electrsd.restart().unwrap();
+        assert!(electrsd.is_running());
+        electrsd.client.ping().unwrap();
+    }
+
+    #[test]
+    fn test_capture_stderr_early_exit() {
+        let (electrs_exe, bitcoind) = setup_bitcoind();
+        let conf = crate::Conf {
+            args: vec!["--not-an-electrs-flag"],
+            capture_stderr: true,
+            attempts: 0,
+            ..Default::default()
+        };
+        let err = match ElectrsD::with_conf(&electrs_exe, &bitcoind, &conf) {
+            Ok(_) => panic!("electrs should exit early"),
+            Err(err) => err,
+        };
+        match err.downcast_ref::<Error>() {
+            Some(Error::EarlyExit {
+                stderr, command, ..
+            }) => {
+                assert!(!stderr.is_empty());
+                assert!(command.contains(&electrs_exe));
+                assert!(command.contains("--not-an-electrs-flag"));
+            }
+            _ => panic!("unexpected error {:?}", err),
+        }
+    }
+
     #[test]
     fn test_kill() {
         let (_, bitcoind, mut electrsd) = setup_nodes();
-        let _ = bitcoind.client.ping().unwrap(); // without using bitcoind, it is dropped and all the rest fails.
-        let _ = electrsd.client.ping().unwrap();
+        bitcoind.client.ping().unwrap(); // without using bitcoind, it is dropped and all the rest fails.
+        electrsd.client.ping().unwrap();
         assert!(electrsd.client.ping().is_ok());
         electrsd.kill().unwrap();
         assert!(electrsd.client.ping().is_err());
     }
 
-    pub(crate) fn setup_nodes() -> (String, bitcoind::BitcoinD, ElectrsD) {
+    #[test]
+    fn test_is_running() {
+        let (_, _bitcoind, mut electrsd) = setup_nodes();
+        assert_ne!(electrsd.pid(), std::process::id());
+        assert!(electrsd.is_running());
+        assert_eq!(electrsd.exit_status().unwrap(), None);
+        electrsd.kill().unwrap();
+        assert!(!electrsd.is_running());
+        assert!(electrsd.exit_status().unwrap().is_some());
+    }
+
+    #[cfg(not(any(target_os = "windows", feature = "electrs_0_8_10")))]
+    #[test]
+    fn test_pause_resume() {
+        let (_, bitcoind, electrsd) = setup_nodes();
+        let header = electrsd.client.block_headers_subscribe().unwrap();
+        assert_eq!(header.height, 1);
+        electrsd.pause().unwrap();
+        let address = bitcoind
+            .client
+            .get_new_address(None, None)
+            .unwrap()
+            .assume_checked();
+        bitcoind.client.generate_to_address(10, &address).unwrap();
+        electrsd.resume().unwrap();
+        #[cfg(not(target_os = "windows"))]
+        electrsd.trigger().unwrap();
+        electrsd.wait_height(11);
+        let header = electrsd.client.block_headers_subscribe().unwrap();
+        assert_eq!(header.height, 11);
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    #[test]
+    fn test_kill_timeout() {
+        let (electrs_exe, bitcoind) = setup_bitcoind();
+        let conf = crate::Conf {
+            kill_timeout: Duration::from_millis(500),
+            ..Default::default()
+        };
+        let mut electrsd = ElectrsD::with_conf(&electrs_exe, &bitcoind, &conf).unwrap();
+        // a stopped process doesn't handle SIGINT
+        electrsd.pause().unwrap();
+        let err = electrsd.kill().unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<Error>(),
+            Some(Error::KillTimeout(waited)) if *waited >= Duration::from_millis(500)
+        ));
+        assert!(electrsd.client.ping().is_err());
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    #[test]
+    fn test_process_group() {
+        use nix::unistd::{getpgid, Pid};
+        let (electrs_exe, bitcoind, electrsd) = setup_nodes();
+        let own_group = getpgid(None).unwrap();
+        let electrs_group =
+            |electrsd: &ElectrsD| getpgid(Some(Pid::from_raw(electrsd.pid() as i32)));
+        assert_eq!(electrs_group(&electrsd).unwrap(), own_group);
+
+        let conf = crate::Conf {
+            process_group: true,
+            ..Default::default()
+        };
+        let electrsd = ElectrsD::with_conf(&electrs_exe, &bitcoind, &conf).unwrap();
+        assert_ne!(electrs_group(&electrsd).unwrap(), own_group);
+    }
+
+    #[test]
+    fn test_monitoring_disabled() {
+        let (electrs_exe, bitcoind) = setup_bitcoind();
+        let conf = crate::Conf {
+            monitoring: false,
+            attempts: 0,
+            ..Default::default()
+        };
+        // both would bind the default monitoring port without an explicit one
+        let first = ElectrsD::with_conf(&electrs_exe, &bitcoind, &conf).unwrap();
+        let second = ElectrsD::with_conf(&electrs_exe, &bitcoind, &conf).unwrap();
+        assert!(first.monitoring_url().is_none());
+        assert!(second.monitoring_url().is_none());
+        first.client.ping().unwrap();
+        second.client.ping().unwrap();
+    }
+
+    #[test]
+    fn test_fixed_electrum_port() {
+        let (electrs_exe, bitcoind) = setup_bitcoind();
+        let port = bitcoind::get_available_port().unwrap();
+        let conf = crate::Conf {
+            electrum_port: Some(port),
+            ..Default::default()
+        };
+        let electrsd = ElectrsD::with_conf(&electrs_exe, &bitcoind, &conf).unwrap();
+        assert_eq!(electrsd.electrum_port(), port);
+
+        match ElectrsD::with_conf(&electrs_exe, &bitcoind, &conf) {
+            Err(e) => assert!(matches!(
+                e.downcast_ref::<Error>(),
+                Some(Error::PortInUse(p)) if *p == port
+            )),
+            Ok(_) => panic!("the port is already used"),
+        }
+    }
+
+    #[test]
+    fn test_startup_timeout() {
+        let (electrs_exe, bitcoind) = setup_bitcoind();
+        let conf = crate::Conf {
+            startup_timeout: Some(Duration::from_nanos(1)),
+            attempts: 0,
+            ..Default::default()
+        };
+        match ElectrsD::with_conf(&electrs_exe, &bitcoind, &conf) {
+            Err(e) => assert!(matches!(
+                e.downcast_ref::<Error>(),
+                Some(Error::StartupTimeout(waited)) if *waited > Duration::from_nanos(1)
+            )),
+            Ok(_) => panic!("electrs can't start in a nanosecond"),
+        }
+    }
+
+    #[test]
+    fn test_tuning_flags() {
+        let (electrs_exe, bitcoind) = setup_bitcoind();
+        // the flags not supported by the enabled version are ignored, the others must be known
+        let conf = crate::Conf {
+            db_parallelism: Some(2),
+            index_batch_size: Some(5),
+            index_lookup_limit: Some(1000),
+            jsonrpc_timeout: Some(Duration::from_secs(30)),
+            attempts: 0,
+            ..Default::default()
+        };
+        let electrsd = ElectrsD::with_conf(&electrs_exe, &bitcoind, &conf).unwrap();
+        assert_eq!(electrsd.generate(&bitcoind, 10).unwrap(), 11);
+    }
+
+    #[test]
+    fn test_daemon_dir() {
+        let (electrs_exe, bitcoind) = setup_bitcoind();
+        let conf = crate::Conf {
+            daemon_dir: Some(bitcoind.workdir()),
+            ..Default::default()
+        };
+        let electrsd = ElectrsD::with_conf(&electrs_exe, &bitcoind, &conf).unwrap();
+        assert_eq!(electrsd.generate(&bitcoind, 10).unwrap(), 11);
+    }
+
+    #[test]
+    fn test_reindex() {
+        let (electrs_exe, bitcoind) = setup_bitcoind();
+        let staticdir = bitcoind::tempfile::tempdir().unwrap();
+        let leftover = staticdir.path().join("leftover");
+        std::fs::write(&leftover, "").unwrap();
+        let conf = crate::Conf {
+            staticdir: Some(staticdir.path().to_path_buf()),
+            reindex: true,
+            ..Default::default()
+        };
+        let _electrsd = ElectrsD::with_conf(&electrs_exe, &bitcoind, &conf).unwrap();
+        assert!(!leftover.exists());
+    }
+
+    #[test]
+    fn test_with_node() {
+        let (bitcoind_exe, electrs_exe) = init();
+        let (bitcoind, electrsd) =
+            ElectrsD::with_node(&electrs_exe, &bitcoind_exe, &crate::Conf::default()).unwrap();
+        assert_eq!(electrsd.generate(&bitcoind, 1).unwrap(), 2);
+    }
+
+    // older versions don't accept connections until bitcoind leaves the initial block download
+    #[cfg(not(any(
+        feature = "electrs_0_8_10",
+        feature = "esplora_a33e97e1",
+        feature = "legacy"
+    )))]
+    #[test]
+    fn test_skip_ibd_check() {
+        let (bitcoind_exe, electrs_exe) = init();
+        let conf = crate::Conf {
+            skip_ibd_check: true,
+            ..Default::default()
+        };
+        let (bitcoind, _electrsd) =
+            ElectrsD::with_node(&electrs_exe, &bitcoind_exe, &conf).unwrap();
+        assert_eq!(bitcoind.client.get_block_count().unwrap(), 0);
+    }
+
+    #[cfg(not(any(
+        feature = "electrs_0_8_10",
+        feature = "esplora_a33e97e1",
+        feature = "legacy"
+    )))]
+    #[test]
+    fn test_p2p_port_required() {
+        let (bitcoind_exe, electrs_exe) = init();
+        let bitcoind = bitcoind::BitcoinD::new(&bitcoind_exe).unwrap();
+        match ElectrsD::new(&electrs_exe, &bitcoind) {
+            Err(e) => assert!(matches!(
+                e.downcast_ref::<Error>(),
+                Some(Error::P2pPortRequired)
+            )),
+            Ok(_) => panic!("bitcoind p2p port is closed"),
+        }
+    }
+
+    #[test]
+    fn test_with_conf_rpc() {
+        let (electrs_exe, bitcoind) = setup_bitcoind();
+        let cookie_file = crate::find_cookie_file(&bitcoind).unwrap();
+        let electrsd = ElectrsD::with_conf_rpc(
+            &electrs_exe,
+            bitcoind.params.rpc_socket.into(),
+            bitcoind.params.p2p_socket.map(Into::into),
+            &cookie_file,
+            &crate::Conf::default(),
+        )
+        .unwrap();
+        assert_eq!(electrsd.generate(&bitcoind, 1).unwrap(), 2);
+    }
+
+    #[test]
+    fn test_stale_cookie() {
+        let (_, bitcoind, mut electrsd) = setup_nodes();
+        assert!(!electrsd.is_cookie_stale());
+        let cookie_file = crate::find_cookie_file(&bitcoind).unwrap();
+        std::fs::write(&cookie_file, "__cookie__:rotated").unwrap();
+        assert_eq!(electrsd.is_cookie_stale(), cfg!(feature = "legacy"));
+        if cfg!(feature = "legacy") {
+            let err = electrsd.restart().unwrap_err();
+            assert!(matches!(
+                err.downcast_ref::<Error>(),
+                Some(Error::StaleCookie { .. })
+            ));
+            assert!(electrsd.is_running());
+        }
+    }
+
+    #[test]
+    fn test_auth_user_pass() {
         let (bitcoind_exe, electrs_exe) = init();
-        debug!("bitcoind: {}", &bitcoind_exe);
-        debug!("electrs: {}", &electrs_exe);
         let mut conf = bitcoind::Conf::default();
-        conf.view_stdout = log_enabled!(Level::Debug);
         if !cfg!(feature = "electrs_0_8_10") && !cfg!(feature = "esplora_a33e97e1") {
             conf.p2p = P2P::Yes;
         }
+        // rpcauth adds the user keeping the cookie, used by the bitcoind client
+        conf.args.push(
+            "-rpcauth=electrsd:0123456789abcdef$59fa6a4dccc441a2ceb3d563296276a0a2293d927d294d695cca0ca3c3556b35",
+        );
         let bitcoind = bitcoind::BitcoinD::with_conf(&bitcoind_exe, &conf).unwrap();
+        let conf = crate::Conf {
+            auth: crate::Auth::UserPass {
+                user: "electrsd",
+                pass: "secret",
+            },
+            ..Default::default()
+        };
+        let electrsd = ElectrsD::with_conf(&electrs_exe, &bitcoind, &conf).unwrap();
+        assert_eq!(electrsd.generate(&bitcoind, 1).unwrap(), 2);
+        assert!(!command_line(&electrsd.command).contains("secret"));
+    }
+
+    #[test]
+    fn test_server_banner() {
+        let (electrs_exe, bitcoind) = setup_bitcoind();
+        let banner = "Welcome ⚡\nsecond line";
+        let conf = crate::Conf {
+            server_banner: Some(banner.to_string()),
+            ..Default::default()
+        };
+        let electrsd = ElectrsD::with_conf(&electrs_exe, &bitcoind, &conf).unwrap();
+        assert!(electrsd.server_banner().unwrap().contains(banner));
+    }
+
+    #[test]
+    fn test_kill_and_keep_data() {
+        let (_, _bitcoind, electrsd) = setup_nodes();
+        assert_eq!(electrsd.workdir_ref(), electrsd.workdir());
+        let path = electrsd.kill_and_keep_data().unwrap();
+        assert!(path.exists());
+        std::fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn test_spawn_hook() {
+        use std::sync::atomic::{AtomicBool, Ordering};
+        let (electrs_exe, bitcoind) = setup_bitcoind();
+        let called = Arc::new(AtomicBool::new(false));
+        let hook_called = called.clone();
+        let conf = crate::Conf {
+            spawn_hook: Some(crate::SpawnHook::new(move |command| {
+                assert!(command.get_args().any(|arg| arg == "--electrum-rpc-addr"));
+                hook_called.store(true, Ordering::SeqCst);
+            })),
+            ..Default::default()
+        };
+        let _electrsd = ElectrsD::with_conf(&electrs_exe, &bitcoind, &conf).unwrap();
+        assert!(called.load(Ordering::SeqCst));
+    }
+
+    #[cfg(not(feature = "electrs_0_8_10"))]
+    #[test]
+    fn test_with_conf_synced() {
+        let (electrs_exe, bitcoind) = setup_bitcoind();
+        let address = bitcoind
+            .client
+            .get_new_address(None, None)
+            .unwrap()
+            .assume_checked();
+        bitcoind.client.generate_to_address(100, &address).unwrap();
+        let electrsd =
+            ElectrsD::with_conf_synced(&electrs_exe, &bitcoind, &crate::Conf::default()).unwrap();
+        // bitcoind already left the initial block download, no block is generated at launch
+        let header = electrsd.client.block_headers_subscribe().unwrap();
+        assert_eq!(header.height, 100);
+    }
+
+    #[test]
+    fn test_new_with_retries() {
+        let (electrs_exe, bitcoind) = setup_bitcoind();
+        let conf = crate::Conf::default();
+        let electrsd = ElectrsD::new_with_retries(&electrs_exe, &bitcoind, &conf, 1).unwrap();
+        electrsd.client.ping().unwrap();
+
+        match ElectrsD::new_with_retries("/nonexistent/electrs", &bitcoind, &conf, 2) {
+            Err(e) => assert!(matches!(
+                e.downcast_ref::<Error>(),
+                Some(Error::AttemptsExhausted { attempts: 3, .. })
+            )),
+            Ok(_) => panic!("the executable doesn't exist"),
+        }
+    }
+
+    #[test]
+    fn test_network_mismatch() {
+        let (electrs_exe, bitcoind) = setup_bitcoind();
+        let conf = crate::Conf {
+            network: "testnet",
+            attempts: 0,
+            ..Default::default()
+        };
+        match ElectrsD::with_conf(&electrs_exe, &bitcoind, &conf) {
+            Err(e) => assert!(matches!(
+                e.downcast_ref::<Error>(),
+                Some(Error::NetworkMismatch { expected, actual })
+                    if expected == "testnet" && actual == "regtest"
+            )),
+            Ok(_) => panic!("bitcoind is on regtest"),
+        }
+
+        // the check is skipped, electrs is launched on testnet and fails on its own
+        let conf = crate::Conf {
+            allow_unknown_network: true,
+            ..conf
+        };
+        if let Err(e) = ElectrsD::with_conf(&electrs_exe, &bitcoind, &conf) {
+            assert!(!matches!(
+                e.downcast_ref::<Error>(),
+                Some(Error::NetworkMismatch { .. })
+            ));
+        }
+    }
+
+    #[test]
+    fn test_listen_addr() {
+        let (electrs_exe, bitcoind) = setup_bitcoind();
+        let conf = crate::Conf {
+            listen_addr: Ipv4Addr::UNSPECIFIED.into(),
+            ..Default::default()
+        };
+        let electrsd = ElectrsD::with_conf(&electrs_exe, &bitcoind, &conf).unwrap();
+        // electrs listens on every interface, it's reached through the loopback
+        assert!(electrsd.electrum_url.starts_with("127.0.0.1:"));
+        electrsd.client.ping().unwrap();
+    }
+
+    #[test]
+    fn test_env() {
+        use std::sync::Mutex;
+        let (electrs_exe, bitcoind) = setup_bitcoind();
+        let envs = Arc::new(Mutex::new(vec![]));
+        let hook_envs = envs.clone();
+        let conf = crate::Conf {
+            env: vec![("RUST_BACKTRACE", "1")],
+            spawn_hook: Some(crate::SpawnHook::new(move |command| {
+                *hook_envs.lock().unwrap() = command
+                    .get_envs()
+                    .map(|(k, v)| (k.to_owned(), v.map(|v| v.to_owned())))
+                    .collect();
+            })),
+            ..Default::default()
+        };
+        let _electrsd = ElectrsD::with_conf(&electrs_exe, &bitcoind, &conf).unwrap();
+        assert_eq!(
+            *envs.lock().unwrap(),
+            vec![("RUST_BACKTRACE".into(), Some("1".into()))]
+        );
+    }
+
+    #[test]
+    fn test_connect_host() {
+        let (electrs_exe, bitcoind, electrsd) = setup_nodes();
+        // connecting to the unspecified address isn't portable, the IPv4 loopback is used
+        assert!(electrsd.electrum_url.starts_with("127.0.0.1:"));
+        assert_eq!(
+            electrsd.electrum_socket(),
+            SocketAddr::new(Ipv4Addr::LOCALHOST.into(), electrsd.electrum_port())
+        );
+
+        let conf = crate::Conf {
+            connect_host: Some("localhost"),
+            ..Default::default()
+        };
+        let electrsd = ElectrsD::with_conf(&electrs_exe, &bitcoind, &conf).unwrap();
+        assert_eq!(
+            electrsd.electrum_url,
+            format!("localhost:{}", electrsd.electrum_port())
+        );
+        electrsd.client.ping().unwrap();
+    }
+
+    #[test]
+    fn test_startup_duration() {
+        let (electrs_exe, bitcoind) = setup_bitcoind();
+        let start = Instant::now();
+        let mut electrsd = ElectrsD::new(&electrs_exe, &bitcoind).unwrap();
+        let launch = electrsd.startup_duration();
+        assert!(launch > Duration::ZERO);
+        assert!(launch <= start.elapsed());
+
+        // after a restart it's the time taken by the new process
+        let start = Instant::now();
+        electrsd.restart().unwrap();
+        assert!(electrsd.startup_duration() > Duration::ZERO);
+        assert!(electrsd.startup_duration() <= start.elapsed());
+    }
+
+    #[test]
+    fn test_verbosity() {
+        let (electrs_exe, bitcoind) = setup_bitcoind();
+        let conf = crate::Conf {
+            verbosity: 2,
+            ..Default::default()
+        };
+        let electrsd = ElectrsD::with_conf(&electrs_exe, &bitcoind, &conf).unwrap();
+        let flags = verbosity_args(2)
+            .iter()
+            .map(|arg| format!("{:?}", arg))
+            .collect::<Vec<_>>()
+            .join(" ");
+        assert!(command_line(&electrsd.command).contains(&flags));
+        electrsd.client.ping().unwrap();
+    }
+
+    pub(crate) fn setup_nodes() -> (String, bitcoind::BitcoinD, ElectrsD) {
+        let (electrs_exe, bitcoind) = setup_bitcoind();
         let electrs_conf = crate::Conf {
             view_stderr: log_enabled!(Level::Debug),
             ..Default::default()
@@ -460,6 +2757,21 @@ mod test {
         (electrs_exe, bitcoind, electrsd)
     }
 
+    /// Returns the electrs executable and a bitcoind ready for it, for the tests launching electrs
+    /// with their own [crate::Conf]
+    pub(crate) fn setup_bitcoind() -> (String, bitcoind::BitcoinD) {
+        let (bitcoind_exe, electrs_exe) = init();
+        debug!("bitcoind: {}", &bitcoind_exe);
+        debug!("electrs: {}", &electrs_exe);
+        let mut conf = bitcoind::Conf::default();
+        conf.view_stdout = log_enabled!(Level::Debug);
+        if !cfg!(feature = "electrs_0_8_10") && !cfg!(feature = "esplora_a33e97e1") {
+            conf.p2p = P2P::Yes;
+        }
+        let bitcoind = bitcoind::BitcoinD::with_conf(&bitcoind_exe, &conf).unwrap();
+        (electrs_exe, bitcoind)
+    }
+
     fn init() -> (String, String) {
         let _ = env_logger::try_init();
         let bitcoind_exe_path = bitcoind::exe_path().unwrap();
@@ -0,0 +1,107 @@
+//! A runtime agnostic async sleep
+//!
+
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Condvar, Mutex, Once};
+use std::task::{Context, Poll, Waker};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// The sleeps waiting for their deadline, woken by a single timer thread started with the first
+static PENDING: Mutex<Vec<(Instant, Arc<Mutex<Waker>>)>> = Mutex::new(Vec::new());
+/// Notifies the timer thread a sleep has been added to [PENDING]
+static ADDED: Condvar = Condvar::new();
+static TIMER: Once = Once::new();
+
+/// A future completing after the given duration, the wake up is scheduled on a timer thread so
+/// that it works with any executor
+pub(crate) struct Sleep {
+    deadline: Instant,
+    waker: Option<Arc<Mutex<Waker>>>,
+}
+
+impl Sleep {
+    pub(crate) fn new(duration: Duration) -> Self {
+        Sleep {
+            deadline: Instant::now() + duration,
+            waker: None,
+        }
+    }
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if Instant::now() >= self.deadline {
+            return Poll::Ready(());
+        }
+        match &self.waker {
+            // the executor may poll with a different waker, the timer must wake the last one
+            Some(waker) => *waker.lock().unwrap() = cx.waker().clone(),
+            None => {
+                let waker = Arc::new(Mutex::new(cx.waker().clone()));
+                schedule(self.deadline, waker.clone());
+                self.waker = Some(waker);
+            }
+        }
+        Poll::Pending
+    }
+}
+
+/// Wake `waker` at `deadline`, a sleep dropped earlier is woken anyway, which is harmless
+fn schedule(deadline: Instant, waker: Arc<Mutex<Waker>>) {
+    TIMER.call_once(|| {
+        thread::spawn(run_timer);
+    });
+    PENDING.lock().unwrap().push((deadline, waker));
+    ADDED.notify_one();
+}
+
+/// Wake the sleeps whose deadline passed, then wait for the next deadline or a new sleep
+fn run_timer() {
+    let mut pending = PENDING.lock().unwrap();
+    loop {
+        let now = Instant::now();
+        let (expired, waiting): (Vec<_>, Vec<_>) = std::mem::take(&mut *pending)
+            .into_iter()
+            .partition(|(deadline, _)| *deadline <= now);
+        *pending = waiting;
+        if !expired.is_empty() {
+            // waking may poll the sleep again, which locks PENDING to schedule itself
+            drop(pending);
+            for (_, waker) in expired {
+                waker.lock().unwrap().wake_by_ref();
+            }
+            pending = PENDING.lock().unwrap();
+            continue;
+        }
+        pending = match pending.iter().map(|(deadline, _)| *deadline).min() {
+            Some(next) => ADDED.wait_timeout(pending, next - now).unwrap().0,
+            None => ADDED.wait(pending).unwrap(),
+        };
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::sleep::Sleep;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_sleep() {
+        let start = Instant::now();
+        crate::block_on(Sleep::new(Duration::from_millis(50)));
+        assert!(start.elapsed() >= Duration::from_millis(50));
+
+        // a shorter sleep scheduled while the timer waits a longer one is woken first
+        let long = thread::spawn(|| crate::block_on(Sleep::new(Duration::from_secs(2))));
+        thread::sleep(Duration::from_millis(50));
+        let start = Instant::now();
+        crate::block_on(Sleep::new(Duration::from_millis(50)));
+        assert!(start.elapsed() < Duration::from_secs(1));
+        long.join().unwrap();
+    }
+}
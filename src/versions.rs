@@ -4,20 +4,29 @@ const OS: &str = "macos";
 #[cfg(target_os = "linux")]
 const OS: &str = "linux";
 
-#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+#[cfg(target_os = "windows")]
+const OS: &str = "windows";
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "windows")))]
 const OS: &str = "undefined";
 
+#[cfg(target_os = "windows")]
+pub const EXE_NAME: &str = "electrs.exe";
+
+#[cfg(not(target_os = "windows"))]
+pub const EXE_NAME: &str = "electrs";
+
 #[cfg(feature = "electrs_0_8_10")]
-const VERSION: &str = "v0.8.10";
+pub const VERSION: &str = "v0.8.10";
 
 #[cfg(feature = "esplora_a33e97e1")]
-const VERSION: &str = "esplora_a33e97e1a1fc63fa9c20a116bb92579bbf43b254";
+pub const VERSION: &str = "esplora_a33e97e1a1fc63fa9c20a116bb92579bbf43b254";
 
 #[cfg(feature = "electrs_0_9_1")]
-const VERSION: &str = "v0.9.1";
+pub const VERSION: &str = "v0.9.1";
 
 #[cfg(feature = "electrs_0_9_11")]
-const VERSION: &str = "v0.9.11";
+pub const VERSION: &str = "v0.9.11";
 
 #[cfg(not(any(
     feature = "electrs_0_8_10",
@@ -25,7 +34,7 @@ const VERSION: &str = "v0.9.11";
     feature = "electrs_0_9_11",
     feature = "esplora_a33e97e1",
 )))]
-const VERSION: &str = "NA";
+pub const VERSION: &str = "NA";
 
 pub const HAS_FEATURE: bool = cfg!(any(
     feature = "electrs_0_8_10",